@@ -109,6 +109,7 @@ use {
         bank_forks::BankForks,
         commitment::BlockCommitmentCache,
         dependency_tracker::DependencyTracker,
+        non_circulating_supply::SupplyMetricsRecorder,
         prioritization_fee_cache::PrioritizationFeeCache,
         runtime_config::RuntimeConfig,
         snapshot_archive_info::SnapshotArchiveInfoGetter,
@@ -978,6 +979,12 @@ impl Validator {
                     .install_scheduler_pool(scheduler_pool);
             }
         }
+        if config.rpc_config.enable_supply_metrics {
+            bank_forks
+                .write()
+                .unwrap()
+                .set_supply_metrics_recorder(Arc::new(SupplyMetricsRecorder::default()));
+        }
 
         let entry_notification_sender = entry_notifier_service
             .as_ref()
@@ -67,7 +67,7 @@ use {
         bank::{Bank, TransactionSimulationResult},
         bank_forks::BankForks,
         commitment::{BlockCommitmentArray, BlockCommitmentCache},
-        non_circulating_supply::{calculate_non_circulating_supply, NonCirculatingSupply},
+        non_circulating_supply::{NonCirculatingSupply, NonCirculatingSupplyCache},
         prioritization_fee_cache::PrioritizationFeeCache,
         snapshot_config::SnapshotConfig,
         snapshot_utils,
@@ -177,6 +177,9 @@ pub struct JsonRpcConfig {
     pub max_request_body_size: Option<usize>,
     /// Disable the health check, used for tests and TestValidator
     pub disable_health_check: bool,
+    /// Record a non-circulating supply datapoint for each newly rooted epoch, which scans the
+    /// stake program
+    pub enable_supply_metrics: bool,
 }
 
 impl Default for JsonRpcConfig {
@@ -197,6 +200,7 @@ impl Default for JsonRpcConfig {
             rpc_scan_and_fix_roots: Default::default(),
             max_request_body_size: Option::default(),
             disable_health_check: Default::default(),
+            enable_supply_metrics: Default::default(),
         }
     }
 }
@@ -250,6 +254,7 @@ pub struct JsonRpcRequestProcessor {
     bigtable_ledger_storage: Option<solana_storage_bigtable::LedgerStorage>,
     optimistically_confirmed_bank: Arc<RwLock<OptimisticallyConfirmedBank>>,
     largest_accounts_cache: Arc<RwLock<LargestAccountsCache>>,
+    non_circulating_supply_cache: Arc<NonCirculatingSupplyCache>,
     max_slots: Arc<MaxSlots>,
     leader_schedule_cache: Arc<LeaderScheduleCache>,
     max_complete_transaction_status_slot: Arc<AtomicU64>,
@@ -295,10 +300,11 @@ impl JsonRpcRequestProcessor {
     async fn calculate_non_circulating_supply(
         &self,
         bank: &Arc<Bank>,
-    ) -> ScanResult<NonCirculatingSupply> {
+    ) -> ScanResult<Arc<NonCirculatingSupply>> {
         let bank = Arc::clone(bank);
+        let cache = Arc::clone(&self.non_circulating_supply_cache);
         self.runtime
-            .spawn_blocking(move || calculate_non_circulating_supply(&bank))
+            .spawn_blocking(move || cache.get_or_calculate(&bank))
             .await
             .expect("Failed to spawn blocking task")
     }
@@ -413,6 +419,7 @@ impl JsonRpcRequestProcessor {
         bigtable_ledger_storage: Option<solana_storage_bigtable::LedgerStorage>,
         optimistically_confirmed_bank: Arc<RwLock<OptimisticallyConfirmedBank>>,
         largest_accounts_cache: Arc<RwLock<LargestAccountsCache>>,
+        non_circulating_supply_cache: Arc<NonCirculatingSupplyCache>,
         max_slots: Arc<MaxSlots>,
         leader_schedule_cache: Arc<LeaderScheduleCache>,
         max_complete_transaction_status_slot: Arc<AtomicU64>,
@@ -435,6 +442,7 @@ impl JsonRpcRequestProcessor {
                 bigtable_ledger_storage,
                 optimistically_confirmed_bank,
                 largest_accounts_cache,
+                non_circulating_supply_cache,
                 max_slots,
                 leader_schedule_cache,
                 max_complete_transaction_status_slot,
@@ -522,6 +530,7 @@ impl JsonRpcRequestProcessor {
             bigtable_ledger_storage: None,
             optimistically_confirmed_bank,
             largest_accounts_cache: Arc::new(RwLock::new(LargestAccountsCache::new(30))),
+            non_circulating_supply_cache: Arc::new(NonCirculatingSupplyCache::default()),
             max_slots: Arc::new(MaxSlots::default()),
             leader_schedule_cache,
             max_complete_transaction_status_slot: Arc::new(AtomicU64::default()),
@@ -1066,7 +1075,7 @@ impl JsonRpcRequestProcessor {
                     .map_err(|e| RpcCustomError::ScanError {
                         message: e.to_string(),
                     })?;
-                let addresses = non_circulating_supply.accounts.into_iter().collect();
+                let addresses = non_circulating_supply.accounts.iter().copied().collect();
                 let address_filter = match filter {
                     RpcLargestAccountsFilter::Circulating => AccountAddressFilter::Exclude,
                     RpcLargestAccountsFilter::NonCirculating => AccountAddressFilter::Include,
@@ -1117,7 +1126,7 @@ impl JsonRpcRequestProcessor {
                 .map_err(|e| RpcCustomError::ScanError {
                     message: e.to_string(),
                 })?;
-        let total_supply = bank.capitalization();
+        let total_supply = non_circulating_supply.total_supply(bank.capitalization());
         let non_circulating_accounts = if config.exclude_non_circulating_accounts_list {
             vec![]
         } else {
@@ -1132,7 +1141,7 @@ impl JsonRpcRequestProcessor {
             &bank,
             RpcSupply {
                 total: total_supply,
-                circulating: total_supply - non_circulating_supply.lamports,
+                circulating: total_supply.saturating_sub(non_circulating_supply.lamports),
                 non_circulating: non_circulating_supply.lamports,
                 non_circulating_accounts,
             },
@@ -4814,6 +4823,7 @@ pub mod tests {
                 None,
                 optimistically_confirmed_bank,
                 Arc::new(RwLock::new(LargestAccountsCache::new(30))),
+                Arc::new(NonCirculatingSupplyCache::default()),
                 max_slots.clone(),
                 Arc::new(LeaderScheduleCache::new_from_bank(&bank)),
                 max_complete_transaction_status_slot.clone(),
@@ -5359,6 +5369,34 @@ pub mod tests {
         assert_eq!(result.value, expected);
     }
 
+    #[test]
+    fn test_get_supply_prewarmed() {
+        let rpc = RpcHandler::start();
+        let bank = rpc.working_bank();
+        rpc.meta
+            .non_circulating_supply_cache
+            .spawn_supply_prewarm(bank.clone())
+            .join()
+            .unwrap();
+
+        // Funding a non-circulating account doesn't show, as the supply is served from the cache
+        bank.process_transaction(&system_transaction::transfer(
+            &rpc.mint_keypair,
+            &non_circulating_accounts()[0],
+            500_000,
+            bank.confirmed_last_blockhash(),
+        ))
+        .expect("process transaction");
+
+        let request = create_test_request(
+            "getSupply",
+            Some(json!([{"excludeNonCirculatingAccountsList": true}])),
+        );
+        let result: RpcResponse<RpcSupply> = parse_success_result(rpc.handle_request_sync(request));
+        assert_eq!(result.value.non_circulating, 0);
+        assert_eq!(result.value.total, bank.capitalization());
+    }
+
     #[test]
     fn test_get_largest_accounts() {
         let rpc = RpcHandler::start();
@@ -6854,6 +6892,7 @@ pub mod tests {
             None,
             optimistically_confirmed_bank,
             Arc::new(RwLock::new(LargestAccountsCache::new(30))),
+            Arc::new(NonCirculatingSupplyCache::default()),
             Arc::new(MaxSlots::default()),
             Arc::new(LeaderScheduleCache::default()),
             Arc::new(AtomicU64::default()),
@@ -7157,6 +7196,7 @@ pub mod tests {
             None,
             optimistically_confirmed_bank,
             Arc::new(RwLock::new(LargestAccountsCache::new(30))),
+            Arc::new(NonCirculatingSupplyCache::default()),
             Arc::new(MaxSlots::default()),
             Arc::new(LeaderScheduleCache::default()),
             Arc::new(AtomicU64::default()),
@@ -8859,6 +8899,7 @@ pub mod tests {
             None,
             optimistically_confirmed_bank.clone(),
             Arc::new(RwLock::new(LargestAccountsCache::new(30))),
+            Arc::new(NonCirculatingSupplyCache::default()),
             Arc::new(MaxSlots::default()),
             Arc::new(LeaderScheduleCache::default()),
             max_complete_transaction_status_slot,
@@ -35,7 +35,7 @@ use {
         bank::Bank,
         bank_forks::BankForks,
         commitment::BlockCommitmentCache,
-        non_circulating_supply::calculate_non_circulating_supply,
+        non_circulating_supply::{calculate_non_circulating_supply, NonCirculatingSupplyCache},
         prioritization_fee_cache::PrioritizationFeeCache,
         snapshot_archive_info::SnapshotArchiveInfoGetter,
         snapshot_config::SnapshotConfig,
@@ -70,6 +70,8 @@ use {
 const FULL_SNAPSHOT_REQUEST_PATH: &str = "/snapshot.tar.bz2";
 const INCREMENTAL_SNAPSHOT_REQUEST_PATH: &str = "/incremental-snapshot.tar.bz2";
 const LARGEST_ACCOUNTS_CACHE_DURATION: u64 = 60 * 60 * 2;
+/// Number of slots a cached non-circulating supply is served for, roughly a minute
+const NON_CIRCULATING_SUPPLY_CACHE_TTL_SLOTS: u64 = 150;
 /// Default minimum snapshot download speed is 10 MB/s
 /// Full snapshots are ~90 GB, incremental are ~1 GB today but both will increase over time
 /// Full: 120 GB / 10 MB/s = 12,000 seconds -> ~30k slots
@@ -732,6 +734,14 @@ impl JsonRpcService {
             LARGEST_ACCOUNTS_CACHE_DURATION,
         )));
 
+        let non_circulating_supply_cache = Arc::new(NonCirculatingSupplyCache::with_ttl_slots(
+            NON_CIRCULATING_SUPPLY_CACHE_TTL_SLOTS,
+        ));
+        bank_forks
+            .write()
+            .unwrap()
+            .set_non_circulating_supply_cache(non_circulating_supply_cache.clone());
+
         let exit_bigtable_ledger_upload_service = Arc::new(AtomicBool::new(false));
 
         let (bigtable_ledger_storage, _bigtable_ledger_upload_service) =
@@ -802,6 +812,7 @@ impl JsonRpcService {
             bigtable_ledger_storage,
             optimistically_confirmed_bank,
             largest_accounts_cache,
+            non_circulating_supply_cache,
             max_slots,
             leader_schedule_cache,
             max_complete_transaction_status_slot,
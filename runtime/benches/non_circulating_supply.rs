@@ -0,0 +1,67 @@
+#![allow(clippy::arithmetic_side_effects)]
+
+use {
+    criterion::{black_box, criterion_group, criterion_main, Criterion},
+    solana_account::Account,
+    solana_genesis_config::GenesisConfig,
+    solana_pubkey::Pubkey,
+    solana_runtime::{
+        bank::Bank,
+        non_circulating_supply::{calculate_non_circulating_supply, NonCirculatingSupplyCache},
+    },
+    solana_stake_interface::{
+        self as stake,
+        state::{Authorized, Lockup, Meta, StakeStateV2},
+    },
+    std::collections::BTreeMap,
+};
+
+const NUM_STAKE_ACCOUNTS: u64 = 50_000;
+
+fn create_bank() -> Bank {
+    let accounts: BTreeMap<Pubkey, Account> = (0..NUM_STAKE_ACCOUNTS)
+        .map(|i| {
+            // Lock up every other account
+            let meta = Meta {
+                authorized: Authorized::auto(&Pubkey::new_unique()),
+                lockup: Lockup {
+                    epoch: i % 2,
+                    ..Lockup::default()
+                },
+                ..Meta::default()
+            };
+            let account = Account::new_data_with_space(
+                1_000 + i,
+                &StakeStateV2::Initialized(meta),
+                StakeStateV2::size_of(),
+                &stake::program::id(),
+            )
+            .unwrap();
+            (Pubkey::new_unique(), account)
+        })
+        .collect();
+    let genesis_config = GenesisConfig {
+        accounts,
+        ..GenesisConfig::default()
+    };
+    Bank::new_for_benches(&genesis_config)
+}
+
+fn bench_non_circulating_supply(c: &mut Criterion) {
+    let bank = create_bank();
+    let cache = NonCirculatingSupplyCache::default();
+    cache.get_or_calculate(&bank).unwrap();
+
+    let mut group = c.benchmark_group("non_circulating_supply");
+    group.sample_size(10);
+    group.bench_function("uncached", |b| {
+        b.iter(|| black_box(calculate_non_circulating_supply(&bank).unwrap()))
+    });
+    group.bench_function("cached", |b| {
+        b.iter(|| black_box(cache.get_or_calculate(&bank).unwrap()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_non_circulating_supply);
+criterion_main!(benches);
@@ -44,6 +44,7 @@ use {
         epoch_stakes::{NodeVoteAccounts, VersionedEpochStakes},
         inflation_rewards::points::InflationPointCalculationEvent,
         installed_scheduler_pool::{BankWithScheduler, InstalledSchedulerRwLock},
+        non_circulating_supply::NonCirculatingSupplyAggregate,
         rent_collector::RentCollector,
         runtime_config::RuntimeConfig,
         snapshot_hash::SnapshotHash,
@@ -538,6 +539,7 @@ impl PartialEq for Bank {
             cluster_type: _,
             rewards_pool_pubkeys: _,
             transaction_debug_keys: _,
+            non_circulating_supply_aggregate: _,
             transaction_log_collector_config: _,
             transaction_log_collector: _,
             feature_set: _,
@@ -831,6 +833,10 @@ pub struct Bank {
 
     transaction_debug_keys: Option<Arc<HashSet<Pubkey>>>,
 
+    /// Running total of non-circulating lamports fed the writes of every slot rooted by squashing
+    /// this bank, shared with the banks descending from it
+    non_circulating_supply_aggregate: Option<Arc<NonCirculatingSupplyAggregate>>,
+
     // Global configuration for how transaction logs should be collected across all banks
     pub transaction_log_collector_config: Arc<RwLock<TransactionLogCollectorConfig>>,
 
@@ -1081,6 +1087,7 @@ impl Bank {
             cluster_type: Option::<ClusterType>::default(),
             rewards_pool_pubkeys: Arc::<HashSet<Pubkey>>::default(),
             transaction_debug_keys: Option::<Arc<HashSet<Pubkey>>>::default(),
+            non_circulating_supply_aggregate: None,
             transaction_log_collector_config: Arc::<RwLock<TransactionLogCollectorConfig>>::default(
             ),
             transaction_log_collector: Arc::<RwLock<TransactionLogCollector>>::default(),
@@ -1328,6 +1335,7 @@ impl Bank {
             cluster_type: parent.cluster_type,
             rewards_pool_pubkeys,
             transaction_debug_keys,
+            non_circulating_supply_aggregate: parent.non_circulating_supply_aggregate.clone(),
             transaction_log_collector_config,
             transaction_log_collector: Arc::new(RwLock::new(TransactionLogCollector::default())),
             feature_set: Arc::clone(&feature_set),
@@ -1793,6 +1801,7 @@ impl Bank {
             cluster_type: Some(genesis_config.cluster_type),
             rewards_pool_pubkeys: Arc::<HashSet<Pubkey>>::default(),
             transaction_debug_keys: debug_keys,
+            non_circulating_supply_aggregate: None,
             transaction_log_collector_config: Arc::<RwLock<TransactionLogCollectorConfig>>::default(
             ),
             transaction_log_collector: Arc::<RwLock<TransactionLogCollector>>::default(),
@@ -2540,6 +2549,10 @@ impl Bank {
         }
         squash_accounts_time.stop();
 
+        if let Some(aggregate) = &self.non_circulating_supply_aggregate {
+            aggregate.on_slots_rooted(self, &roots);
+        }
+
         *self.rc.parent.write().unwrap() = None;
 
         let mut squash_cache_time = Measure::start("squash_cache_time");
@@ -5981,6 +5994,15 @@ impl Bank {
         self.fee_structure = fee_structure.clone();
     }
 
+    /// Feeds the writes of every slot rooted from now on, by squashing this bank or the banks
+    /// descending from it, to `aggregate`
+    pub fn set_non_circulating_supply_aggregate(
+        &mut self,
+        aggregate: Arc<NonCirculatingSupplyAggregate>,
+    ) {
+        self.non_circulating_supply_aggregate = Some(aggregate);
+    }
+
     pub fn load_program(
         &self,
         pubkey: &Pubkey,
@@ -7,6 +7,7 @@ use {
         installed_scheduler_pool::{
             BankWithScheduler, InstalledSchedulerPoolArc, SchedulingContext,
         },
+        non_circulating_supply::{NonCirculatingSupplyCache, SupplyMetricsRecorder},
         snapshot_controller::SnapshotController,
     },
     arc_swap::ArcSwap,
@@ -88,6 +89,8 @@ pub struct BankForks {
     highest_slot_at_startup: Slot,
     scheduler_pool: Option<InstalledSchedulerPoolArc>,
     dumped_slot_subscribers: Vec<DumpedSlotSubscription>,
+    supply_metrics_recorder: Option<Arc<SupplyMetricsRecorder>>,
+    non_circulating_supply_cache: Option<Arc<NonCirculatingSupplyCache>>,
 }
 
 impl Index<u64> for BankForks {
@@ -137,6 +140,8 @@ impl BankForks {
             highest_slot_at_startup: 0,
             scheduler_pool: None,
             dumped_slot_subscribers: vec![],
+            supply_metrics_recorder: None,
+            non_circulating_supply_cache: None,
         }));
 
         root_bank.set_fork_graph_in_program_cache(Arc::downgrade(&bank_forks));
@@ -231,6 +236,16 @@ impl BankForks {
         );
     }
 
+    /// Records the supply whenever the root enters a new epoch
+    pub fn set_supply_metrics_recorder(&mut self, recorder: Arc<SupplyMetricsRecorder>) {
+        self.supply_metrics_recorder = Some(recorder);
+    }
+
+    /// Prewarms `cache` with the supply of the root bank whenever the root enters a new epoch
+    pub fn set_non_circulating_supply_cache(&mut self, cache: Arc<NonCirculatingSupplyCache>) {
+        self.non_circulating_supply_cache = Some(cache);
+    }
+
     pub fn insert(&mut self, bank: Bank) -> BankWithScheduler {
         self.insert_with_scheduling_mode(SchedulingMode::BlockVerification, bank)
     }
@@ -387,6 +402,12 @@ impl BankForks {
                  {root}"
             );
             root_bank.clear_epoch_rewards_cache();
+            if let Some(recorder) = &self.supply_metrics_recorder {
+                recorder.spawn_record_new_epoch(root_bank.clone());
+            }
+            if let Some(cache) = &self.non_circulating_supply_cache {
+                cache.spawn_supply_prewarm(root_bank.clone());
+            }
         }
         let root_tx_count = root_bank
             .parents()
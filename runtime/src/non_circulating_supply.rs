@@ -1,220 +1,6709 @@
 use {
-    crate::bank::Bank,
+    crate::{bank::Bank, bank_forks::BankForks, commitment::BlockCommitmentCache},
     log::*,
-    solana_account::ReadableAccount,
-    solana_accounts_db::accounts_index::{AccountIndex, IndexKey, ScanConfig, ScanResult},
+    rayon::iter::{IntoParallelRefIterator, ParallelIterator},
+    solana_account::{from_account, AccountSharedData, ReadableAccount},
+    solana_accounts_db::{
+        accounts_index::{AccountIndex, IndexKey, ScanConfig, ScanError, ScanResult},
+        ancestors::Ancestors,
+    },
+    solana_clock::{BankId, Clock, Epoch, Slot},
+    solana_commitment_config::CommitmentLevel,
+    solana_epoch_schedule::EpochSchedule,
     solana_pubkey::Pubkey,
-    solana_stake_interface::{self as stake, state::StakeStateV2},
+    solana_rent::Rent,
+    solana_sdk_ids::incinerator,
+    solana_sha256_hasher::Hasher,
+    solana_stake_interface::{
+        self as stake,
+        stake_history::StakeHistory,
+        state::{Lockup, StakeStateV2},
+        sysvar,
+    },
     solana_stake_program::stake_state,
-    std::collections::HashSet,
+    std::{
+        cmp::{Ordering, Reverse},
+        collections::{BTreeMap, BinaryHeap, HashMap, HashSet},
+        fmt, iter, mem,
+        ops::Range,
+        panic::{self, AssertUnwindSafe},
+        sync::{
+            atomic::{self, AtomicUsize},
+            mpsc::Sender,
+            Arc,
+        },
+    },
+    thiserror::Error,
 };
 
+mod aggregate;
+mod cache;
+mod config_file;
+mod feed;
+mod json;
+mod metrics;
+mod mmap;
+mod openmetrics;
+mod pinned;
+mod reason;
+mod source;
+#[cfg(feature = "tokio")]
+mod stream;
+mod vesting;
+use reason::stake_account_reason;
+#[cfg(feature = "tokio")]
+pub use stream::non_circulating_stream;
+pub use {
+    aggregate::NonCirculatingSupplyAggregate,
+    cache::NonCirculatingSupplyCache,
+    config_file::ConfigFileError,
+    feed::GrpcSupplyBuilder,
+    json::calculate_and_write_json,
+    metrics::SupplyMetricsRecorder,
+    mmap::{write_to_mmap, MMAP_HEADER_LEN},
+    openmetrics::supply_openmetrics,
+    pinned::PinnedAccountsView,
+    reason::{
+        FrozenAccountClassifier, LinearVestingClassifier, NonCirculatingReason, ReasonClassifier,
+        StakeReasonClassifier, TokenLockClassifier,
+    },
+    source::{calculate_non_circulating_supply_from, AccountsSnapshot, SupplySource},
+    vesting::{apply_external_vesting, VestingSchedule},
+};
+
+#[derive(Clone)]
 pub struct NonCirculatingSupply {
     pub lamports: u64,
     pub accounts: Vec<Pubkey>,
+    /// Why each account counted in `lamports` is non-circulating
+    pub reasons: HashMap<Pubkey, NonCirculatingReason>,
+    /// Balance of each account counted in `lamports`
+    pub balances: HashMap<Pubkey, u64>,
+    /// Lamports held by the incinerator, which are removed from the total supply rather than
+    /// counted as non-circulating when `incinerator_as_burned` is set
+    pub burned_lamports: u64,
+    /// Accounts collapsed out of `accounts` by `consolidate_dust_below`
+    pub dust: Option<ConsolidatedDust>,
+    /// Stake accounts whose data failed to deserialize, which are counted as circulating under
+    /// `DecodeFailureMode::Lenient`
+    pub undecodable_accounts: Vec<Pubkey>,
+    /// Accounts returned by the scan of a program they aren't owned by, which are skipped rather
+    /// than decoded as that program's accounts
+    pub misowned_accounts: Vec<Pubkey>,
+    /// Accounts counted as circulating because decoding or classifying them panicked, with
+    /// `catch_decode_panics` set
+    pub panicked_decodes: usize,
+    /// Stake of the non-circulating stake accounts that is still activating, with
+    /// `report_activating_stake` set
+    pub activating_lamports: u64,
+    /// Deactivating stake counted as non-circulating, with `count_cooldown_stake` set
+    pub cooldown_lamports: u64,
+    /// Circulating lamports held by the `foundation_reserve` accounts, which
+    /// `circulating_supply()` leaves out
+    pub reserve_lamports: u64,
+    /// Counted lamports attributable to the compiled-in lists: the hardcoded accounts of
+    /// `non_circulating_accounts()` and the stake accounts counted for a withdraw authority of
+    /// `withdraw_authority()`
+    pub static_lamports: u64,
+    /// Counted lamports attributable to accounts and withdraw authorities added at runtime, e.g.
+    /// through the config or `NonCirculatingSupplyCache::add_hardcoded_account()`
+    pub dynamic_lamports: u64,
+    /// Entries of `withdraw_authority()` that aren't the withdraw authority of any stake account
+    /// the calculation examined, and so could be pruned from the list. Left empty by the
+    /// calculations that only examine some of the stake accounts.
+    pub stale_authorities: Vec<Pubkey>,
+    /// Part of the increase of the non-circulating stake balances attributable to staking
+    /// rewards, as computed by `calculate_non_circulating_supply_across_rewards()`. Left at zero
+    /// by the calculations over a single bank.
+    pub locked_rewards_lamports: u64,
+    /// Lockup of each stake account counted for it, against which
+    /// `recompute_on_epoch_change()` finds the accounts whose lockup has expired
+    pub lockups: HashMap<Pubkey, Lockup>,
+    /// Epoch of the bank the supply was calculated for
+    pub epoch: Epoch,
+}
+
+impl fmt::Display for NonCirculatingSupply {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} non-circulating lamports in {} accounts at epoch {}",
+            self.lamports,
+            self.accounts.len(),
+            self.epoch
+        )?;
+        if let Some(dust) = self.dust {
+            write!(
+                f,
+                ", including {} lamports in {} dust accounts",
+                dust.lamports, dust.count
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// How to handle a stake account whose data fails to deserialize
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecodeFailureMode {
+    /// Count the account as circulating and report it in `undecodable_accounts`
+    #[default]
+    Lenient,
+    /// Abort the calculation
+    Strict,
+}
+
+enum AccountClassification {
+    Circulating,
+    Undecodable,
+    Misowned,
+    Panicked,
+    NonCirculating {
+        reason: NonCirculatingReason,
+        partial_lamports: Option<u64>,
+    },
+}
+
+/// Synthetic entry standing in for the sub-threshold accounts omitted from the accounts list. Their
+/// lamports remain included in the non-circulating total.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConsolidatedDust {
+    pub lamports: u64,
+    pub count: usize,
+}
+
+/// The compiled-in `non_circulating_accounts()` and `withdraw_authority()` lists, built once per
+/// calculation, against which counted lamports are attributed to `static_lamports` or
+/// `dynamic_lamports`
+struct StaticLists {
+    accounts: HashSet<Pubkey>,
+    withdraw_authorities: HashSet<Pubkey>,
+}
+
+impl StaticLists {
+    fn new() -> Self {
+        Self {
+            accounts: non_circulating_accounts().into_iter().collect(),
+            withdraw_authorities: withdraw_authority().into_iter().collect(),
+        }
+    }
+
+    /// Whether the lamports of `pubkey`, counted for `reason`, are attributed to
+    /// `static_lamports` rather than `dynamic_lamports`. Only hardcoded accounts and stake
+    /// accounts counted for their withdraw authority, `withdrawer`, are attributed at all.
+    fn is_static(
+        &self,
+        pubkey: &Pubkey,
+        reason: NonCirculatingReason,
+        withdrawer: Option<&Pubkey>,
+    ) -> Option<bool> {
+        match reason {
+            NonCirculatingReason::Hardcoded => Some(self.accounts.contains(pubkey)),
+            NonCirculatingReason::WithdrawAuthority => Some(
+                withdrawer.is_some_and(|withdrawer| self.withdraw_authorities.contains(withdrawer)),
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl NonCirculatingSupply {
+    /// Assembles a supply from raw parts, for testing consumers without scanning a bank
+    #[cfg(test)]
+    pub(crate) fn new_for_tests(
+        lamports: u64,
+        accounts: Vec<Pubkey>,
+        reasons: Option<HashMap<Pubkey, NonCirculatingReason>>,
+        balances: Option<HashMap<Pubkey, u64>>,
+    ) -> Self {
+        Self {
+            lamports,
+            accounts,
+            reasons: reasons.unwrap_or_default(),
+            balances: balances.unwrap_or_default(),
+            burned_lamports: 0,
+            dust: None,
+            undecodable_accounts: vec![],
+            misowned_accounts: vec![],
+            panicked_decodes: 0,
+            activating_lamports: 0,
+            cooldown_lamports: 0,
+            reserve_lamports: 0,
+            static_lamports: 0,
+            dynamic_lamports: 0,
+            stale_authorities: vec![],
+            locked_rewards_lamports: 0,
+            lockups: HashMap::new(),
+            epoch: 0,
+        }
+    }
+
+    /// Returns the total supply given the bank's `capitalization`, excluding burned lamports
+    pub fn total_supply(&self, capitalization: u64) -> u64 {
+        capitalization.saturating_sub(self.burned_lamports)
+    }
+
+    /// Returns the circulating supply given the bank's `capitalization`, net of the foundation
+    /// reserve
+    pub fn circulating_supply(&self, capitalization: u64) -> u64 {
+        self.total_supply(capitalization)
+            .saturating_sub(self.lamports)
+            .saturating_sub(self.reserve_lamports)
+    }
+
+    /// Formats the non-circulating share of `total` as a percentage with `decimals` decimal
+    /// places, e.g. "12.50%", or 0% if `total` is zero
+    pub fn percentage_string(&self, total: u64, decimals: u8) -> String {
+        let pct = if total == 0 {
+            0.0
+        } else {
+            self.lamports as f64 / total as f64 * 100.0
+        };
+        format!("{pct:.*}%", usize::from(decimals))
+    }
+
+    /// Estimates the memory held by the supply, including the heap allocations of its accounts
+    /// list, reason, balance and lockup maps and undecodable accounts, e.g. to decide whether to
+    /// cache it.
+    /// Hash map entries are counted with one control byte each, as in hashbrown.
+    pub fn estimated_size_bytes(&self) -> usize {
+        let map_entry_size = |value_size: usize| mem::size_of::<Pubkey>() + value_size + 1;
+        mem::size_of::<Self>()
+            + self.accounts.capacity() * mem::size_of::<Pubkey>()
+            + self.reasons.capacity() * map_entry_size(mem::size_of::<NonCirculatingReason>())
+            + self.balances.capacity() * map_entry_size(mem::size_of::<u64>())
+            + self.lockups.capacity() * map_entry_size(mem::size_of::<Lockup>())
+            + self.undecodable_accounts.capacity() * mem::size_of::<Pubkey>()
+    }
+
+    /// Folds the results of another chunk of a resumable scan into `self`
+    pub fn merge(&mut self, other: NonCirculatingSupply) {
+        self.lamports += other.lamports;
+        self.accounts.extend(other.accounts);
+        self.reasons.extend(other.reasons);
+        self.balances.extend(other.balances);
+        self.burned_lamports += other.burned_lamports;
+        if let Some(other_dust) = other.dust {
+            let dust = self.dust.get_or_insert_with(ConsolidatedDust::default);
+            dust.lamports += other_dust.lamports;
+            dust.count += other_dust.count;
+        }
+        self.undecodable_accounts.extend(other.undecodable_accounts);
+        self.misowned_accounts.extend(other.misowned_accounts);
+        self.panicked_decodes += other.panicked_decodes;
+        self.activating_lamports += other.activating_lamports;
+        self.cooldown_lamports += other.cooldown_lamports;
+        self.reserve_lamports += other.reserve_lamports;
+        self.static_lamports += other.static_lamports;
+        self.dynamic_lamports += other.dynamic_lamports;
+        // Stale only if no chunk has an account controlled by it
+        self.stale_authorities
+            .retain(|authority| other.stale_authorities.contains(authority));
+        self.locked_rewards_lamports += other.locked_rewards_lamports;
+        self.lockups.extend(other.lockups);
+    }
+
+    /// Adds `lamports`, counted for `pubkey` for `reason`, to `static_lamports` or
+    /// `dynamic_lamports` as `static_lists` attributes them
+    fn attribute_lamports(
+        &mut self,
+        static_lists: &StaticLists,
+        pubkey: &Pubkey,
+        reason: NonCirculatingReason,
+        withdrawer: Option<&Pubkey>,
+        lamports: u64,
+    ) {
+        match static_lists.is_static(pubkey, reason, withdrawer) {
+            Some(true) => self.static_lamports += lamports,
+            Some(false) => self.dynamic_lamports += lamports,
+            None => {}
+        }
+    }
+
+    /// Counts `pubkey` as a hardcoded non-circulating account holding `balance`. An account that
+    /// is already counted only has its reason updated.
+    fn include_hardcoded_account(
+        &mut self,
+        static_lists: &StaticLists,
+        pubkey: Pubkey,
+        balance: u64,
+    ) {
+        if self
+            .reasons
+            .insert(pubkey, NonCirculatingReason::Hardcoded)
+            .is_none()
+        {
+            self.lamports += balance;
+            self.accounts.push(pubkey);
+            self.balances.insert(pubkey, balance);
+            self.attribute_lamports(
+                static_lists,
+                &pubkey,
+                NonCirculatingReason::Hardcoded,
+                None,
+                balance,
+            );
+        }
+    }
+
+    /// Counts `lamports` of stake account `pubkey` as non-circulating for `reason`
+    fn include_stake_account(
+        &mut self,
+        static_lists: &StaticLists,
+        pubkey: Pubkey,
+        reason: NonCirculatingReason,
+        account: &AccountSharedData,
+        lamports: u64,
+    ) {
+        self.lamports += lamports;
+        self.accounts.push(pubkey);
+        self.reasons.insert(pubkey, reason);
+        self.balances.insert(pubkey, lamports);
+        let meta = stake_state::meta_from(account);
+        if reason == NonCirculatingReason::Lockup {
+            if let Some(meta) = meta {
+                self.lockups.insert(pubkey, meta.lockup);
+            }
+        }
+        let withdrawer = meta.map(|meta| meta.authorized.withdrawer);
+        self.attribute_lamports(static_lists, &pubkey, reason, withdrawer.as_ref(), lamports);
+    }
+
+    /// Updates the supply, calculated against a bank of the previous epoch under `config`, for
+    /// `bank`'s epoch. Crossing an epoch boundary only changes the status of stake accounts whose
+    /// lockup has expired, so only the accounts counted for a lockup no longer in force are
+    /// fetched and reclassified under `config`, rather than scanning the stake program again.
+    /// Balances are carried over, so they remain net of the rent-exempt reserve if `config`
+    /// excludes it.
+    ///
+    /// Under a config whose amounts change every epoch regardless of lockups, or whose total is
+    /// scaled, see `NonCirculatingSupplyConfig::is_incremental()`, the supply is calculated
+    /// anew instead.
+    pub fn recompute_on_epoch_change(
+        &mut self,
+        bank: &Bank,
+        config: &NonCirculatingSupplyConfig,
+    ) -> ScanResult<()> {
+        if !config.is_incremental() {
+            *self = calculate_non_circulating_supply_with_config(bank, config)?;
+            return Ok(());
+        }
+        let stake_classifier = config.stake_classifier();
+        let clock = config.lockup_clock(bank);
+        let static_lists = StaticLists::new();
+        let expired: Vec<Pubkey> = self
+            .lockups
+            .iter()
+            .filter(|(pubkey, lockup)| {
+                self.reasons.get(pubkey) == Some(&NonCirculatingReason::Lockup)
+                    && !lockup.is_in_force(&clock, None)
+            })
+            .map(|(pubkey, _lockup)| *pubkey)
+            .collect();
+        for pubkey in expired {
+            self.lockups.remove(&pubkey);
+            let account = bank.get_account(&pubkey);
+            let reason = account
+                .as_ref()
+                .and_then(|account| stake_classifier.classify(&pubkey, account, &clock));
+            let balance = self.balances.get(&pubkey).copied().unwrap_or_default();
+            match (reason, account) {
+                (Some(reason), Some(account)) => {
+                    self.reasons.insert(pubkey, reason);
+                    let meta = stake_state::meta_from(&account);
+                    if reason == NonCirculatingReason::Lockup {
+                        // Extended by its custodian
+                        if let Some(meta) = meta {
+                            self.lockups.insert(pubkey, meta.lockup);
+                        }
+                    }
+                    let withdrawer = meta.map(|meta| meta.authorized.withdrawer);
+                    self.attribute_lamports(
+                        &static_lists,
+                        &pubkey,
+                        reason,
+                        withdrawer.as_ref(),
+                        balance,
+                    );
+                }
+                _ => {
+                    self.reasons.remove(&pubkey);
+                    self.balances.remove(&pubkey);
+                    self.lamports = self.lamports.saturating_sub(balance);
+                    if let Some(index) = self.accounts.iter().position(|listed| *listed == pubkey) {
+                        self.accounts.swap_remove(index);
+                    } else if let Some(dust) = self.dust.as_mut() {
+                        dust.lamports = dust.lamports.saturating_sub(balance);
+                        dust.count = dust.count.saturating_sub(1);
+                    }
+                }
+            }
+        }
+        self.epoch = bank.epoch();
+        Ok(())
+    }
+
+    /// Sums the counted lamports of the accounts non-circulating for each reason
+    pub fn lamports_by_reason(&self) -> BTreeMap<NonCirculatingReason, u64> {
+        let mut totals = BTreeMap::new();
+        for (pubkey, reason) in self.reasons.iter() {
+            *totals.entry(*reason).or_default() +=
+                self.balances.get(pubkey).copied().unwrap_or_default();
+        }
+        totals
+    }
+
+    /// Hashes the counted accounts and their balances, in pubkey order, together with the total,
+    /// so that two supplies can be compared without shipping the accounts
+    pub fn checksum(&self) -> [u8; 32] {
+        let mut balances: Vec<(&Pubkey, &u64)> = self.balances.iter().collect();
+        balances.sort_unstable();
+        let mut hasher = Hasher::default();
+        hasher.hash(&self.lamports.to_le_bytes());
+        for (pubkey, balance) in balances {
+            hasher.hash(pubkey.as_ref());
+            hasher.hash(&balance.to_le_bytes());
+        }
+        hasher.result().to_bytes()
+    }
+
+    /// Checks that the reasons partition the counted accounts: every listed account has a reason,
+    /// every account with a reason has a balance and vice versa, and the per-reason sums add up
+    /// to the unscaled total
+    fn verify_reason_partition(&self) -> ScanResult<()> {
+        let mut listed = HashSet::with_capacity(self.accounts.len());
+        for pubkey in self.accounts.iter() {
+            if !listed.insert(pubkey) {
+                return Err(ScanError::Aborted(format!(
+                    "non-circulating account {pubkey} is listed more than once"
+                )));
+            }
+            if !self.reasons.contains_key(pubkey) {
+                return Err(ScanError::Aborted(format!(
+                    "non-circulating account {pubkey} has no reason"
+                )));
+            }
+        }
+        if let Some(pubkey) = self
+            .reasons
+            .keys()
+            .chain(self.balances.keys())
+            .find(|pubkey| {
+                !self.reasons.contains_key(*pubkey) || !self.balances.contains_key(*pubkey)
+            })
+        {
+            return Err(ScanError::Aborted(format!(
+                "non-circulating account {pubkey} lacks either a reason or a balance"
+            )));
+        }
+        let reason_lamports = self
+            .lamports_by_reason()
+            .into_values()
+            .fold(0u64, u64::saturating_add);
+        if reason_lamports != self.lamports {
+            return Err(ScanError::Aborted(format!(
+                "per-reason lamports sum to {reason_lamports}, not the total of {}",
+                self.lamports
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the Gini coefficient of the per-account balances, from 0.0 when all accounts hold
+    /// the same balance towards 1.0 when a single account holds everything. Returns 0.0 when
+    /// there are fewer than two accounts or no lamports.
+    pub fn balance_gini(&self) -> f64 {
+        let mut balances: Vec<u64> = self.balances.values().copied().collect();
+        let total: u128 = balances.iter().map(|balance| u128::from(*balance)).sum();
+        if balances.len() < 2 || total == 0 {
+            return 0.0;
+        }
+        balances.sort_unstable();
+        let weighted_sum: u128 = balances
+            .iter()
+            .zip(1u128..)
+            .map(|(balance, rank)| rank * u128::from(*balance))
+            .sum();
+        let count = balances.len() as f64;
+        2.0 * weighted_sum as f64 / (count * total as f64) - (count + 1.0) / count
+    }
+}
+
+/// One chunk of a resumable non-circulating supply scan
+pub struct NonCirculatingSupplyChunk {
+    pub supply: NonCirculatingSupply,
+    /// Last stake account processed, to be passed to the next call, or `None` once all stake
+    /// accounts have been processed
+    pub cursor: Option<Pubkey>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct NonCirculatingSupplyConfig {
+    /// Only consider stake accounts written within this slot range, e.g. to analyze the locked
+    /// supply of a single distribution event. AccountsDb only retains the slot of an account's
+    /// latest write, so this is the creation slot only for accounts not modified since.
+    pub stake_account_slots: Option<Range<Slot>>,
+    /// Treat the incinerator's balance as already burned, removing it from the total supply
+    /// instead of counting it as non-circulating
+    pub incinerator_as_burned: bool,
+    /// Ignore stake accounts holding fewer lamports, dropping them from both the accounts list
+    /// and the total
+    pub min_lamports: Option<u64>,
+    /// Also omit hardcoded accounts holding fewer than `min_lamports` from the accounts list. As
+    /// the hardcoded list is authoritative, their lamports are still included in the total.
+    pub exclude_dust_hardcoded_from_list: bool,
+    /// Classifiers consulted after the built-in `StakeReasonClassifier`
+    pub classifiers: Vec<Arc<dyn ReasonClassifier>>,
+    /// Evaluate lockup epochs against the epoch this schedule assigns to the bank's slot rather
+    /// than the bank's own epoch, for what-if analyses of different epoch lengths
+    pub epoch_schedule: Option<EpochSchedule>,
+    /// Replace accounts holding fewer lamports in the accounts list with a single
+    /// `ConsolidatedDust` entry
+    pub consolidate_dust_below: Option<u64>,
+    /// Don't count the rent-exempt reserve of classified program accounts as non-circulating
+    pub exclude_rent_exempt_reserve: bool,
+    /// Rent used to size the excluded reserves, defaulting to the bank's
+    pub rent: Option<Rent>,
+    /// Classify accounts on the rayon thread pool
+    pub parallel: bool,
+    pub decode_failure_mode: DecodeFailureMode,
+    /// Treat lockups with a custodian as liftable, and so circulating, unless the custodian is
+    /// in this set
+    pub hard_custodians: Option<Vec<Pubkey>>,
+    /// Evaluate lockups against this clock instead of the bank's, e.g. to project a future
+    /// supply from current balances
+    pub clock: Option<Clock>,
+    /// Count panics while decoding or classifying an account, e.g. from a classifier for an
+    /// alternative stake program, in `panicked_decodes` instead of propagating them
+    pub catch_decode_panics: bool,
+    /// Report the stake of non-circulating stake accounts that is still warming up in
+    /// `activating_lamports`
+    pub report_activating_stake: bool,
+    /// Factor applied to the reported total, e.g. for testnet dashboards valuing tokens
+    /// differently. Per-account balances are reported unscaled.
+    pub scale: LamportsScale,
+    /// Verify that the reasons partition the counted accounts and lamports, aborting the
+    /// calculation otherwise
+    pub verify_reason_partition: bool,
+    /// Allowlist of accounts counted as hardcoded non-circulating on top of
+    /// `non_circulating_accounts()`. See `account_rule()` for how it interacts with the other
+    /// rules.
+    pub additional_non_circulating_accounts: Vec<Pubkey>,
+    /// Withdraw authorities making stake non-circulating on top of `withdraw_authority()`
+    pub additional_withdraw_authorities: Vec<Pubkey>,
+    /// Count stake accounts matching this predicate as circulating even if classified as
+    /// non-circulating, e.g. accounts flagged by a migration
+    pub exclude_stake_accounts: Option<StakeAccountPredicate>,
+    /// Count stake accounts matching this predicate as non-circulating even if classified as
+    /// circulating
+    pub include_stake_accounts: Option<StakeAccountPredicate>,
+    /// Denylist of accounts counted as circulating, whether hardcoded or classified as
+    /// non-circulating
+    pub denied_accounts: Vec<Pubkey>,
+    /// Accounts explicitly excluded from the non-circulating supply, overriding every other rule
+    pub excluded_accounts: Vec<Pubkey>,
+    /// Count the deactivating stake of otherwise circulating stake accounts still in their
+    /// cooldown as non-circulating, as it isn't freely usable yet
+    pub count_cooldown_stake: bool,
+    /// Accounts of a foundation reserve which, although circulating, are reported separately in
+    /// `reserve_lamports` and left out of `circulating_supply()`
+    pub foundation_reserve: Vec<Pubkey>,
+    /// Scan the accounts of each classified program a second time, bypassing the program id
+    /// account index, and abort unless both scans agree. This is an expensive audit mode,
+    /// doubling the cost of the calculation. See `calculate_non_circulating_supply_verified()`
+    /// for the `DivergenceError` found.
+    pub verify_double_scan: bool,
+    /// Read the accounts from this view rather than from the bank, so that the calculation doesn't
+    /// see the writes made to the bank while it runs. See `PinnedAccountsView` for what the view
+    /// doesn't isolate. Sysvars, such as the clock, are still read from the bank.
+    pub pinned_view: Option<PinnedAccountsView>,
+    /// Evaluate unix-timestamp lockups as of this wall-clock time, e.g. for historical reports,
+    /// overriding only the timestamp of the clock lockups are evaluated against
+    pub as_of_unix: Option<i64>,
+    /// Count the balance of the hardcoded `PROTOCOL_FEE_ACCOUNT` as circulating, while still
+    /// counting the other hardcoded accounts
+    pub protocol_fees_as_circulating: bool,
+    /// Abort the calculation, before scanning, if a hardcoded account, including the ones in
+    /// `additional_non_circulating_accounts`, doesn't exist, e.g. because of a misconfigured
+    /// genesis
+    pub require_hardcoded_present: bool,
+    /// Only consider delegated stake accounts, in the `StakeStateV2::Stake` state, counting
+    /// `Initialized` ones as circulating, e.g. to analyze delegated holdings alone
+    pub delegated_stake_only: bool,
+}
+
+/// Outcome of the account rules of a `NonCirculatingSupplyConfig`, from the highest precedence
+/// to the lowest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountRule {
+    /// Listed in `excluded_accounts`: circulating
+    Excluded,
+    /// Listed in `denied_accounts` or matching `exclude_stake_accounts`: circulating
+    Denied,
+    /// Listed in `additional_non_circulating_accounts` or matching `include_stake_accounts`:
+    /// non-circulating
+    Allowed,
+    /// No rule applies, leaving the account to the hardcoded list and the classifiers
+    Default,
+}
+
+impl AccountRule {
+    /// Reason of the stake accounts only counted because of `include_stake_accounts`
+    pub const INCLUDED_REASON: &'static str = "included";
+}
+
+impl NonCirculatingSupplyConfig {
+    /// Evaluates the account rules for `pubkey`. The stake account predicates are only
+    /// evaluated for stake accounts, given as `stake_account`.
+    ///
+    /// Precedence is, from highest to lowest: the explicit `excluded_accounts`, then the
+    /// `denied_accounts` denylist and the `exclude_stake_accounts` predicate, then the
+    /// `additional_non_circulating_accounts` allowlist and the `include_stake_accounts`
+    /// predicate, and finally the default classification.
+    pub fn account_rule(
+        &self,
+        pubkey: &Pubkey,
+        stake_account: Option<&AccountSharedData>,
+    ) -> AccountRule {
+        let matches = |predicate: &Option<StakeAccountPredicate>| {
+            predicate
+                .as_ref()
+                .zip(stake_account)
+                .is_some_and(|(predicate, account)| predicate.matches(pubkey, account))
+        };
+        if self.excluded_accounts.contains(pubkey) {
+            AccountRule::Excluded
+        } else if self.denied_accounts.contains(pubkey) || matches(&self.exclude_stake_accounts) {
+            AccountRule::Denied
+        } else if self.additional_non_circulating_accounts.contains(pubkey)
+            || matches(&self.include_stake_accounts)
+        {
+            AccountRule::Allowed
+        } else {
+            AccountRule::Default
+        }
+    }
+
+    /// Whether a supply calculated under this config can be carried across an epoch boundary by
+    /// `NonCirculatingSupply::recompute_on_epoch_change()`. Cooldown and activating stake, and
+    /// the amounts of custom classifiers, e.g. `LinearVestingClassifier`, change every epoch,
+    /// and a scaled total can't be adjusted by the balances of individual accounts.
+    pub fn is_incremental(&self) -> bool {
+        !self.count_cooldown_stake
+            && !self.report_activating_stake
+            && self.classifiers.is_empty()
+            && self.scale == LamportsScale::default()
+    }
+
+    /// Builds the classifier of stake accounts against the compiled-in and additional withdraw
+    /// authorities, honoring `hard_custodians`
+    fn stake_classifier(&self) -> StakeReasonClassifier {
+        let mut withdraw_authority_list = withdraw_authority();
+        withdraw_authority_list.extend(self.additional_withdraw_authorities.iter().copied());
+        let stake_classifier = StakeReasonClassifier::new(withdraw_authority_list);
+        match &self.hard_custodians {
+            Some(hard_custodians) => stake_classifier.with_hard_custodians(hard_custodians.clone()),
+            None => stake_classifier,
+        }
+    }
+
+    /// Returns the clock the lockups of `bank`'s accounts are evaluated against, with the
+    /// `clock`, `epoch_schedule` and `as_of_unix` overrides applied
+    fn lockup_clock(&self, bank: &Bank) -> Clock {
+        let mut clock = self.clock.clone().unwrap_or_else(|| bank.clock());
+        if let Some(epoch_schedule) = &self.epoch_schedule {
+            clock.epoch = epoch_schedule.get_epoch(clock.slot);
+        }
+        if let Some(as_of_unix) = self.as_of_unix {
+            clock.unix_timestamp = as_of_unix;
+        }
+        clock
+    }
+}
+
+/// Predicate over a stake account and its pubkey
+#[derive(Clone)]
+pub struct StakeAccountPredicate(Arc<dyn Fn(&Pubkey, &AccountSharedData) -> bool + Send + Sync>);
+
+impl StakeAccountPredicate {
+    pub fn new(
+        predicate: impl Fn(&Pubkey, &AccountSharedData) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(predicate))
+    }
+
+    pub fn matches(&self, pubkey: &Pubkey, account: &AccountSharedData) -> bool {
+        (self.0)(pubkey, account)
+    }
+}
+
+impl fmt::Debug for StakeAccountPredicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StakeAccountPredicate")
+            .finish_non_exhaustive()
+    }
+}
+
+/// Factor of `numerator / denominator` applied to a lamports amount
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LamportsScale {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl Default for LamportsScale {
+    fn default() -> Self {
+        Self {
+            numerator: 1,
+            denominator: 1,
+        }
+    }
+}
+
+impl LamportsScale {
+    /// Scales `lamports`, rounding down and saturating at `u64::MAX`, including for a zero
+    /// denominator
+    pub fn apply(&self, lamports: u64) -> u64 {
+        (u128::from(lamports) * u128::from(self.numerator))
+            .checked_div(u128::from(self.denominator))
+            .map_or(u64::MAX, |scaled| u64::try_from(scaled).unwrap_or(u64::MAX))
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum NonCirculatingSupplyError {
+    #[error(transparent)]
+    Scan(#[from] ScanError),
+    #[error("lamports controlled by withdraw authority {0} overflow u64")]
+    AuthorityLamportsOverflow(Pubkey),
+    #[error(transparent)]
+    Divergence(#[from] DivergenceError),
+}
+
+/// Two scans of the accounts of a program disagree on an account, with `verify_double_scan` set
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("scans of the accounts of program {program_id} diverge at account {pubkey}")]
+pub struct DivergenceError {
+    pub program_id: Pubkey,
+    pub pubkey: Pubkey,
 }
 
 pub fn calculate_non_circulating_supply(bank: &Bank) -> ScanResult<NonCirculatingSupply> {
+    trace_calculation(bank, || {
+        calculate_non_circulating_supply_with_config(bank, &NonCirculatingSupplyConfig::default())
+    })
+}
+
+/// Runs `calculate` within a `non_circulating_supply` span recording the bank's slot, and the
+/// number of accounts, lamports and duration of the calculation once it succeeds
+#[cfg(feature = "tracing")]
+fn trace_calculation(
+    bank: &Bank,
+    calculate: impl FnOnce() -> ScanResult<NonCirculatingSupply>,
+) -> ScanResult<NonCirculatingSupply> {
+    use {std::time::Instant, tracing::field::Empty};
+
+    let span = tracing::info_span!(
+        "non_circulating_supply",
+        slot = bank.slot(),
+        accounts = Empty,
+        lamports = Empty,
+        duration_us = Empty,
+    );
+    let _entered = span.enter();
+    let start = Instant::now();
+    let result = calculate();
+    if let Ok(supply) = &result {
+        span.record("accounts", supply.accounts.len() as u64);
+        span.record("lamports", supply.lamports);
+        span.record("duration_us", start.elapsed().as_micros() as u64);
+    }
+    result
+}
+
+#[cfg(not(feature = "tracing"))]
+fn trace_calculation(
+    _bank: &Bank,
+    calculate: impl FnOnce() -> ScanResult<NonCirculatingSupply>,
+) -> ScanResult<NonCirculatingSupply> {
+    calculate()
+}
+
+pub fn calculate_non_circulating_supply_with_config(
+    bank: &Bank,
+    config: &NonCirculatingSupplyConfig,
+) -> ScanResult<NonCirculatingSupply> {
+    calculate_non_circulating_supply_impl(bank, config, ScanOptions::default())
+}
+
+/// Calculates the non-circulating supply like `calculate_non_circulating_supply_with_config()`,
+/// also returning the pubkeys of all stake accounts the scan examined, circulating or not, so
+/// that the scan can serve other processing of the stake accounts
+pub fn calculate_non_circulating_supply_and_scanned_stake_accounts(
+    bank: &Bank,
+    config: &NonCirculatingSupplyConfig,
+) -> ScanResult<(NonCirculatingSupply, Vec<Pubkey>)> {
+    let mut scanned_stake_accounts = vec![];
+    let options = ScanOptions {
+        scanned_stake_accounts: Some(&mut scanned_stake_accounts),
+        ..ScanOptions::default()
+    };
+    let supply = calculate_non_circulating_supply_impl(bank, config, options)?;
+    Ok((supply, scanned_stake_accounts))
+}
+
+/// Number of scanned accounts between two progress messages
+const SCAN_PROGRESS_INTERVAL: usize = 10_000;
+
+/// Progress of a non-circulating supply scan
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScanProgress {
+    /// Program accounts scanned so far
+    pub scanned_accounts: usize,
+    /// Accounts found to be non-circulating so far, hardcoded ones included
+    pub matched_accounts: usize,
+}
+
+/// Calculates the non-circulating supply like `calculate_non_circulating_supply_with_config()`,
+/// sending the scan's progress to `progress` every `SCAN_PROGRESS_INTERVAL` accounts loaded, and
+/// again every `SCAN_PROGRESS_INTERVAL` accounts classified, as well as once the scan completes,
+/// e.g. for a live progress display. The calculation carries on if the receiver is dropped.
+pub fn calculate_non_circulating_supply_with_progress(
+    bank: &Bank,
+    config: &NonCirculatingSupplyConfig,
+    progress: Sender<ScanProgress>,
+) -> ScanResult<NonCirculatingSupply> {
+    let options = ScanOptions {
+        progress: Some((&progress, SCAN_PROGRESS_INTERVAL)),
+        ..ScanOptions::default()
+    };
+    calculate_non_circulating_supply_impl(bank, config, options)
+}
+
+/// Calculates the non-circulating supply like `calculate_non_circulating_supply_with_config()`
+/// with `verify_double_scan` set, returning the `DivergenceError` if the two scans of a program's
+/// accounts disagree
+pub fn calculate_non_circulating_supply_verified(
+    bank: &Bank,
+    config: &NonCirculatingSupplyConfig,
+) -> Result<NonCirculatingSupply, NonCirculatingSupplyError> {
+    let config = NonCirculatingSupplyConfig {
+        verify_double_scan: true,
+        ..config.clone()
+    };
+    let mut divergence = None;
+    let options = ScanOptions {
+        divergence: Some(&mut divergence),
+        ..ScanOptions::default()
+    };
+    calculate_non_circulating_supply_impl(bank, &config, options).map_err(|err| match divergence {
+        Some(divergence) => NonCirculatingSupplyError::Divergence(divergence),
+        None => NonCirculatingSupplyError::Scan(err),
+    })
+}
+
+/// Calculates the non-circulating supply of `bank` as if `overrides` had been written to it,
+/// without committing them, e.g. to simulate the effect of pending transactions. An override
+/// with zero lamports deletes the account, as for any other write.
+pub fn calculate_with_overrides(
+    bank: &Bank,
+    overrides: &HashMap<Pubkey, AccountSharedData>,
+) -> ScanResult<NonCirculatingSupply> {
+    let options = ScanOptions {
+        overrides: Some(overrides),
+        ..ScanOptions::default()
+    };
+    calculate_non_circulating_supply_impl(bank, &NonCirculatingSupplyConfig::default(), options)
+}
+
+/// Applies `overrides` to the accounts of `program_id`, dropping the accounts the overrides
+/// delete or reassign to another program and adding the ones they assign to it
+fn apply_overrides(
+    program_accounts: &mut Vec<(Pubkey, AccountSharedData)>,
+    program_id: &Pubkey,
+    overrides: &HashMap<Pubkey, AccountSharedData>,
+) {
+    let is_program_account =
+        |account: &AccountSharedData| account.lamports() > 0 && account.owner() == program_id;
+    let mut overridden = HashSet::new();
+    program_accounts.retain_mut(|(pubkey, account)| match overrides.get(pubkey) {
+        Some(override_account) => {
+            overridden.insert(*pubkey);
+            *account = override_account.clone();
+            is_program_account(account)
+        }
+        None => true,
+    });
+    program_accounts.extend(
+        overrides
+            .iter()
+            .filter(|(pubkey, account)| {
+                !overridden.contains(*pubkey) && is_program_account(account)
+            })
+            .map(|(pubkey, account)| (*pubkey, account.clone())),
+    );
+}
+
+/// Optional inputs and side outputs of a supply calculation
+#[derive(Default)]
+struct ScanOptions<'a> {
+    scanned_stake_accounts: Option<&'a mut Vec<Pubkey>>,
+    /// Receiver of the scan's progress, and the number of accounts between two messages
+    progress: Option<(&'a Sender<ScanProgress>, usize)>,
+    /// Uncommitted account writes read in place of the bank's accounts
+    overrides: Option<&'a HashMap<Pubkey, AccountSharedData>>,
+    /// Set to the divergence `verify_double_scan` aborts the scan for
+    divergence: Option<&'a mut Option<DivergenceError>>,
+}
+
+fn calculate_non_circulating_supply_impl(
+    bank: &Bank,
+    config: &NonCirculatingSupplyConfig,
+    mut options: ScanOptions,
+) -> ScanResult<NonCirculatingSupply> {
     debug!("Updating Bank supply, epoch: {}", bank.epoch());
-    let mut non_circulating_accounts_set: HashSet<Pubkey> = HashSet::new();
+    let mut hardcoded_accounts = non_circulating_accounts();
+    if config.protocol_fees_as_circulating {
+        hardcoded_accounts.retain(|pubkey| *pubkey != PROTOCOL_FEE_ACCOUNT);
+    }
+    hardcoded_accounts.extend(config.additional_non_circulating_accounts.iter().copied());
+    hardcoded_accounts.retain(|pubkey| {
+        matches!(
+            config.account_rule(pubkey, None),
+            AccountRule::Allowed | AccountRule::Default
+        )
+    });
+    let get_balance = |pubkey: &Pubkey| {
+        if let Some(account) = options
+            .overrides
+            .and_then(|overrides| overrides.get(pubkey))
+        {
+            return account.lamports();
+        }
+        match &config.pinned_view {
+            Some(view) => view.get_balance(pubkey),
+            None => bank.get_balance(pubkey),
+        }
+    };
+    if config.require_hardcoded_present {
+        // Accounts without lamports don't exist
+        if let Some(pubkey) = hardcoded_accounts
+            .iter()
+            .find(|pubkey| get_balance(pubkey) == 0)
+        {
+            return Err(ScanError::Aborted(format!(
+                "hardcoded non-circulating account {pubkey} doesn't exist"
+            )));
+        }
+    }
+    let mut reasons: HashMap<Pubkey, NonCirculatingReason> = hardcoded_accounts
+        .iter()
+        .map(|pubkey| (*pubkey, NonCirculatingReason::Hardcoded))
+        .collect();
+
+    let stake_classifier = config.stake_classifier();
+    let classifiers: Vec<&dyn ReasonClassifier> =
+        iter::once(&stake_classifier as &dyn ReasonClassifier)
+            .chain(
+                config
+                    .classifiers
+                    .iter()
+                    .map(|classifier| classifier.as_ref()),
+            )
+            .collect();
+    let mut program_ids: Vec<Pubkey> = vec![];
+    for classifier in classifiers.iter() {
+        if !program_ids.contains(&classifier.program_id()) {
+            program_ids.push(classifier.program_id());
+        }
+    }
+
+    let clock = config.lockup_clock(bank);
+    let rent = config
+        .rent
+        .clone()
+        .unwrap_or_else(|| bank.rent_collector().rent.clone());
+    // Accounts of which only part of the balance is counted
+    let mut partial_lamports: HashMap<Pubkey, u64> = HashMap::new();
+    let mut undecodable_accounts = vec![];
+    let mut misowned_accounts = vec![];
+    let mut panicked_decodes = 0;
+    let stake_history: Option<StakeHistory> =
+        (config.report_activating_stake || config.count_cooldown_stake).then(|| {
+            bank.get_account(&sysvar::stake_history::id())
+                .and_then(|account| from_account(&account))
+                .unwrap_or_default()
+        });
+    let new_rate_activation_epoch = bank.new_warmup_cooldown_rate_epoch();
+    let mut activating_lamports = 0;
+    let mut cooldown_lamports = 0;
+    let static_lists = StaticLists::new();
+    let mut stale_authorities = withdraw_authority();
+    // Withdraw authority of each account counted for it
+    let mut withdrawers = HashMap::new();
+    let mut lockups = HashMap::new();
+    let mut scanned_accounts = 0;
+    let progress_interval = options
+        .progress
+        .map_or(usize::MAX, |(_progress, interval)| interval);
+    let send_progress = |scanned_accounts, matched_accounts| {
+        if let Some((progress, _interval)) = options.progress {
+            // The receiver hanging up doesn't affect the calculation
+            let _ = progress.send(ScanProgress {
+                scanned_accounts,
+                matched_accounts,
+            });
+        }
+    };
+    let (ancestors, bank_id) = match &config.pinned_view {
+        Some(view) => view.ancestors(),
+        None => (&bank.ancestors, bank.bank_id()),
+    };
+    for program_id in program_ids.iter() {
+        let program_classifiers: Vec<&dyn ReasonClassifier> = classifiers
+            .iter()
+            .copied()
+            .filter(|classifier| classifier.program_id() == *program_id)
+            .collect();
+        let classify_account = |(pubkey, account): &(Pubkey, AccountSharedData)| {
+            if account.owner() != program_id {
+                return AccountClassification::Misowned;
+            }
+            if let Some(slots) = &config.stake_account_slots {
+                let written_slot = bank
+                    .get_account_modified_slot(pubkey)
+                    .map(|(_account, slot)| slot);
+                if !written_slot.is_some_and(|slot| slots.contains(&slot)) {
+                    return AccountClassification::Circulating;
+                }
+            }
+            if config
+                .min_lamports
+                .is_some_and(|min_lamports| account.lamports() < min_lamports)
+            {
+                return AccountClassification::Circulating;
+            }
+            if reasons.contains_key(pubkey) {
+                return AccountClassification::Circulating;
+            }
+            let is_stake_account = *program_id == stake::program::id();
+            if config.delegated_stake_only
+                && is_stake_account
+                && stake_state::from(account)
+                    .is_some_and(|state| !matches!(state, StakeStateV2::Stake(..)))
+            {
+                return AccountClassification::Circulating;
+            }
+            match config.account_rule(pubkey, is_stake_account.then_some(account)) {
+                AccountRule::Excluded | AccountRule::Denied => {
+                    return AccountClassification::Circulating;
+                }
+                AccountRule::Allowed => {
+                    return AccountClassification::NonCirculating {
+                        reason: NonCirculatingReason::Custom(AccountRule::INCLUDED_REASON),
+                        partial_lamports: None,
+                    };
+                }
+                AccountRule::Default => {}
+            }
+            if is_stake_account && stake_state::from(account).is_none() {
+                return AccountClassification::Undecodable;
+            }
+            let Some((reason, non_circulating_lamports)) = program_classifiers
+                .iter()
+                .find_map(|classifier| {
+                    let reason = classifier.classify(pubkey, account, &clock)?;
+                    Some((
+                        reason,
+                        classifier.non_circulating_lamports(pubkey, account, &clock),
+                    ))
+                })
+                .or_else(|| {
+                    if !config.count_cooldown_stake || !is_stake_account {
+                        return None;
+                    }
+                    let deactivating = stake_state::delegation_from(account)?
+                        .stake_activating_and_deactivating(
+                            clock.epoch,
+                            stake_history.as_ref()?,
+                            new_rate_activation_epoch,
+                        )
+                        .deactivating;
+                    (deactivating > 0).then_some((NonCirculatingReason::Cooldown, deactivating))
+                })
+            else {
+                return AccountClassification::Circulating;
+            };
+            // The delegated stake of an account in cooldown already excludes its reserve
+            let non_circulating_lamports = if config.exclude_rent_exempt_reserve
+                && reason != NonCirculatingReason::Cooldown
+            {
+                non_circulating_lamports.saturating_sub(rent.minimum_balance(account.data().len()))
+            } else {
+                non_circulating_lamports
+            };
+            AccountClassification::NonCirculating {
+                reason,
+                partial_lamports: (non_circulating_lamports != account.lamports())
+                    .then_some(non_circulating_lamports),
+            }
+        };
+        let loaded_accounts = AtomicUsize::new(0);
+        let mut program_accounts =
+            load_program_accounts_at(bank, ancestors, bank_id, program_id, || {
+                let loaded_accounts = loaded_accounts.fetch_add(1, atomic::Ordering::Relaxed) + 1;
+                if loaded_accounts % progress_interval == 0 {
+                    send_progress(scanned_accounts + loaded_accounts, reasons.len());
+                }
+            })?;
+        if let Some(scanned) = options
+            .scanned_stake_accounts
+            .as_mut()
+            .filter(|_| *program_id == stake::program::id())
+        {
+            scanned.extend(program_accounts.iter().map(|(pubkey, _account)| *pubkey));
+        }
+        if config.verify_double_scan {
+            if let Err(err) =
+                verify_double_scan(bank, ancestors, bank_id, program_id, &program_accounts)?
+            {
+                let message = err.to_string();
+                if let Some(divergence) = options.divergence.as_mut() {
+                    **divergence = Some(err);
+                }
+                return Err(ScanError::Aborted(message));
+            }
+        }
+        if let Some(overrides) = options.overrides {
+            apply_overrides(&mut program_accounts, program_id, overrides);
+        }
+        if *program_id == stake::program::id() {
+            for (_pubkey, account) in program_accounts.iter() {
+                retain_stale_authorities(&mut stale_authorities, account);
+            }
+        }
+        scanned_accounts += program_accounts.len();
+        let classified_accounts = AtomicUsize::new(0);
+        let matched_accounts = AtomicUsize::new(0);
+        let classify_account = |entry: &(Pubkey, AccountSharedData)| {
+            let classification = if config.catch_decode_panics {
+                panic::catch_unwind(AssertUnwindSafe(|| classify_account(entry)))
+                    .unwrap_or(AccountClassification::Panicked)
+            } else {
+                classify_account(entry)
+            };
+            if matches!(classification, AccountClassification::NonCirculating { .. }) {
+                matched_accounts.fetch_add(1, atomic::Ordering::Relaxed);
+            }
+            let classified_accounts =
+                classified_accounts.fetch_add(1, atomic::Ordering::Relaxed) + 1;
+            if classified_accounts % progress_interval == 0 {
+                send_progress(
+                    scanned_accounts,
+                    reasons.len() + matched_accounts.load(atomic::Ordering::Relaxed),
+                );
+            }
+            classification
+        };
+        // Workers only classify; failures are gathered with the results and handled once all
+        // of them have joined
+        let classifications: Vec<AccountClassification> = if config.parallel {
+            program_accounts.par_iter().map(classify_account).collect()
+        } else {
+            program_accounts.iter().map(classify_account).collect()
+        };
+        for ((pubkey, account), classification) in program_accounts.iter().zip(classifications) {
+            match classification {
+                AccountClassification::Circulating => {}
+                AccountClassification::Panicked => panicked_decodes += 1,
+                AccountClassification::Misowned => {
+                    warn!("Skipping account {pubkey} not owned by scanned program {program_id}");
+                    misowned_accounts.push(*pubkey);
+                }
+                AccountClassification::Undecodable => match config.decode_failure_mode {
+                    DecodeFailureMode::Lenient => undecodable_accounts.push(*pubkey),
+                    DecodeFailureMode::Strict => {
+                        return Err(ScanError::Aborted(format!(
+                            "failed to deserialize stake account {pubkey}"
+                        )));
+                    }
+                },
+                AccountClassification::NonCirculating {
+                    reason,
+                    partial_lamports: partial,
+                } => {
+                    reasons.insert(*pubkey, reason);
+                    let meta = (account.owner() == &stake::program::id())
+                        .then(|| stake_state::meta_from(account))
+                        .flatten();
+                    match (reason, meta) {
+                        (NonCirculatingReason::WithdrawAuthority, Some(meta)) => {
+                            withdrawers.insert(*pubkey, meta.authorized.withdrawer);
+                        }
+                        (NonCirculatingReason::Lockup, Some(meta)) => {
+                            lockups.insert(*pubkey, meta.lockup);
+                        }
+                        _ => {}
+                    }
+                    if let Some(partial) = partial {
+                        partial_lamports.insert(*pubkey, partial);
+                    }
+                    if reason == NonCirculatingReason::Cooldown {
+                        cooldown_lamports += partial.unwrap_or_else(|| account.lamports());
+                    }
+                    if let Some(stake_history) = stake_history
+                        .as_ref()
+                        .filter(|_| config.report_activating_stake)
+                    {
+                        activating_lamports +=
+                            stake_state::delegation_from(account).map_or(0, |delegation| {
+                                delegation
+                                    .stake_activating_and_deactivating(
+                                        clock.epoch,
+                                        stake_history,
+                                        new_rate_activation_epoch,
+                                    )
+                                    .activating
+                            });
+                    }
+                }
+            }
+        }
+    }
+    send_progress(scanned_accounts, reasons.len());
+
+    let burned_lamports = if config.incinerator_as_burned {
+        reasons.remove(&incinerator::id());
+        get_balance(&incinerator::id())
+    } else {
+        0
+    };
+
+    let mut lamports = 0;
+    let mut static_lamports = 0;
+    let mut dynamic_lamports = 0;
+    let mut accounts = Vec::with_capacity(reasons.len());
+    let mut balances = HashMap::with_capacity(reasons.len());
+    let mut dust = None;
+    for (pubkey, reason) in reasons.iter() {
+        let pubkey = *pubkey;
+        let balance = partial_lamports
+            .get(&pubkey)
+            .copied()
+            .unwrap_or_else(|| get_balance(&pubkey));
+        lamports += balance;
+        balances.insert(pubkey, balance);
+        match static_lists.is_static(&pubkey, *reason, withdrawers.get(&pubkey)) {
+            Some(true) => static_lamports += balance,
+            Some(false) => dynamic_lamports += balance,
+            None => {}
+        }
+        let is_unlisted_dust = config.exclude_dust_hardcoded_from_list
+            && config
+                .min_lamports
+                .is_some_and(|min_lamports| balance < min_lamports)
+            && hardcoded_accounts.contains(&pubkey);
+        if is_unlisted_dust {
+            continue;
+        }
+        if config
+            .consolidate_dust_below
+            .is_some_and(|threshold| balance < threshold)
+        {
+            let entry = dust.get_or_insert_with(ConsolidatedDust::default);
+            entry.lamports += balance;
+            entry.count += 1;
+        } else {
+            accounts.push(pubkey);
+        }
+    }
+
+    let mut supply = NonCirculatingSupply {
+        lamports,
+        accounts,
+        reasons,
+        balances,
+        burned_lamports,
+        dust,
+        undecodable_accounts,
+        misowned_accounts,
+        panicked_decodes,
+        activating_lamports,
+        cooldown_lamports,
+        reserve_lamports: 0,
+        static_lamports,
+        dynamic_lamports,
+        stale_authorities,
+        locked_rewards_lamports: 0,
+        lockups,
+        epoch: bank.epoch(),
+    };
+    for pubkey in config.foundation_reserve.iter().collect::<HashSet<_>>() {
+        let counted = supply.balances.get(pubkey).copied().unwrap_or_default();
+        supply.reserve_lamports += get_balance(pubkey).saturating_sub(counted);
+    }
+    if config.verify_reason_partition {
+        supply.verify_reason_partition()?;
+    }
+    supply.lamports = config.scale.apply(supply.lamports);
+    Ok(supply)
+}
+
+/// Projects the non-circulating supply at the start of the next epoch from current balances,
+/// evaluating lockups against the clock `clock_at_epoch()` synthesizes for the next epoch
+pub fn next_epoch_projection(bank: &Bank) -> ScanResult<NonCirculatingSupply> {
+    let clock = clock_at_epoch(bank, bank.epoch() + 1);
+    let config = NonCirculatingSupplyConfig {
+        clock: Some(clock),
+        ..NonCirculatingSupplyConfig::default()
+    };
+    calculate_non_circulating_supply_with_config(bank, &config)
+}
+
+/// Calculates the non-circulating supply of `bank` with lockups evaluated at the first slot of
+/// its epoch and the epoch's start timestamp, giving a figure that only changes with balances
+/// over the epoch, e.g. for epoch-aligned reports
+pub fn non_circulating_supply_epoch_start(bank: &Bank) -> ScanResult<NonCirculatingSupply> {
+    let clock = bank.clock();
+    let clock = Clock {
+        slot: bank.epoch_schedule().get_first_slot_in_epoch(clock.epoch),
+        unix_timestamp: clock.epoch_start_timestamp,
+        ..clock
+    };
+    let config = NonCirculatingSupplyConfig {
+        clock: Some(clock),
+        ..NonCirculatingSupplyConfig::default()
+    };
+    calculate_non_circulating_supply_with_config(bank, &config)
+}
+
+/// Calculates the non-circulating supply of `after`, attributing to `locked_rewards_lamports` the
+/// staking rewards paid to its non-circulating stake accounts since `before`, e.g. the banks on
+/// either side of an epoch's rewards distribution. Rewards are paid into the delegated stake, so
+/// an account's attributable increase is the growth of its delegation, capped at the growth of
+/// its balance; deposits, which only grow the balance, aren't attributed.
+pub fn calculate_non_circulating_supply_across_rewards(
+    before: &Bank,
+    after: &Bank,
+) -> ScanResult<NonCirculatingSupply> {
+    let mut supply = calculate_non_circulating_supply(after)?;
+    let mut locked_rewards_lamports = 0u64;
+    for (pubkey, reason) in supply.reasons.iter() {
+        if !matches!(
+            reason,
+            NonCirculatingReason::Lockup | NonCirculatingReason::WithdrawAuthority
+        ) {
+            continue;
+        }
+        let (Some(before_account), Some(after_account)) =
+            (before.get_account(pubkey), after.get_account(pubkey))
+        else {
+            continue;
+        };
+        let (Some(before_delegation), Some(after_delegation)) = (
+            stake_state::delegation_from(&before_account),
+            stake_state::delegation_from(&after_account),
+        ) else {
+            continue;
+        };
+        let stake_increase = after_delegation
+            .stake
+            .saturating_sub(before_delegation.stake);
+        let balance_increase = after_account
+            .lamports()
+            .saturating_sub(before_account.lamports());
+        locked_rewards_lamports += stake_increase.min(balance_increase);
+    }
+    supply.locked_rewards_lamports = locked_rewards_lamports;
+    Ok(supply)
+}
+
+/// Synthesizes the clock at the first slot of `epoch` under the bank's `EpochSchedule`, e.g. to
+/// project the supply of a future epoch. The timestamps are extrapolated from the bank's clock at
+/// its nominal slot duration.
+pub fn clock_at_epoch(bank: &Bank, epoch: Epoch) -> Clock {
+    let epoch_schedule = bank.epoch_schedule();
+    let slot = epoch_schedule.get_first_slot_in_epoch(epoch);
+    let clock = bank.clock();
+    let elapsed_ns = (i128::from(slot) - i128::from(bank.slot())) * bank.ns_per_slot as i128;
+    let unix_timestamp = clock
+        .unix_timestamp
+        .saturating_add((elapsed_ns / 1_000_000_000) as i64);
+    Clock {
+        slot,
+        epoch_start_timestamp: unix_timestamp,
+        epoch,
+        leader_schedule_epoch: epoch_schedule.get_leader_schedule_epoch(slot),
+        unix_timestamp,
+    }
+}
+
+/// Calculates the non-circulating supply over at most `max_stake_accounts` stake accounts, in
+/// pubkey order, following `cursor`, so that a large scan can be split up and resumed after an
+/// interruption. Merging all chunks, starting from a `None` cursor, yields the same supply as a
+/// single-pass calculation; the hardcoded accounts are accounted for in the first chunk.
+///
+/// AccountsDb doesn't expose a scan over a pubkey range, so each chunk still loads all stake
+/// accounts, but only classifies the ones that belong to it.
+pub fn calculate_non_circulating_supply_chunk(
+    bank: &Bank,
+    cursor: Option<Pubkey>,
+    max_stake_accounts: usize,
+) -> ScanResult<NonCirculatingSupplyChunk> {
+    let mut stake_accounts = load_stake_accounts(bank)?;
+    stake_accounts.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    let hardcoded_accounts = non_circulating_accounts();
+    let withdraw_authority_list = withdraw_authority();
+    let static_lists = StaticLists::new();
+    let clock = bank.clock();
+
+    let mut supply = NonCirculatingSupply {
+        lamports: 0,
+        accounts: vec![],
+        reasons: HashMap::new(),
+        balances: HashMap::new(),
+        burned_lamports: 0,
+        dust: None,
+        undecodable_accounts: vec![],
+        misowned_accounts: vec![],
+        panicked_decodes: 0,
+        activating_lamports: 0,
+        cooldown_lamports: 0,
+        reserve_lamports: 0,
+        static_lamports: 0,
+        dynamic_lamports: 0,
+        // Narrowed down to the authorities of none of the chunk's accounts below
+        stale_authorities: withdraw_authority_list.clone(),
+        locked_rewards_lamports: 0,
+        lockups: HashMap::new(),
+        epoch: bank.epoch(),
+    };
+    if cursor.is_none() {
+        for pubkey in hardcoded_accounts.iter() {
+            supply.include_hardcoded_account(&static_lists, *pubkey, bank.get_balance(pubkey));
+        }
+    }
+
+    let start = cursor.map_or(0, |cursor| {
+        stake_accounts.partition_point(|(pubkey, _account)| *pubkey <= cursor)
+    });
+    let mut remaining = stake_accounts[start..]
+        .iter()
+        .filter(|(pubkey, _account)| !hardcoded_accounts.contains(pubkey))
+        .peekable();
+    let mut last_processed = None;
+    for (pubkey, account) in remaining.by_ref().take(max_stake_accounts) {
+        retain_stale_authorities(&mut supply.stale_authorities, account);
+        if let Some(reason) = stake_account_reason(account, &clock, &withdraw_authority_list) {
+            supply.include_stake_account(
+                &static_lists,
+                *pubkey,
+                reason,
+                account,
+                account.lamports(),
+            );
+        }
+        last_processed = Some(*pubkey);
+    }
+    let cursor = if remaining.peek().is_some() {
+        last_processed
+    } else {
+        None
+    };
+    Ok(NonCirculatingSupplyChunk { supply, cursor })
+}
+
+/// Calculates the non-circulating supply as observed at `commitment`, selecting the bank from
+/// `bank_forks` through `block_commitment_cache` and falling back to the root bank if the
+/// selected slot is no longer present
+pub fn calculate_non_circulating_supply_with_commitment(
+    bank_forks: &BankForks,
+    block_commitment_cache: &BlockCommitmentCache,
+    commitment: CommitmentLevel,
+) -> ScanResult<NonCirculatingSupply> {
+    let slot = block_commitment_cache.slot_with_commitment(commitment);
+    let bank = bank_forks
+        .get(slot)
+        .unwrap_or_else(|| bank_forks.root_bank());
+    calculate_non_circulating_supply(&bank)
+}
+
+/// Maps each future epoch to the lamports of currently locked up stake whose lockup expires at
+/// that epoch. Stake controlled by a hardcoded withdraw authority doesn't become circulating when
+/// its lockup expires and is excluded, as is stake whose unlock epoch is unknown because its
+/// unix timestamp lockup is still in force.
+pub fn unlock_schedule(bank: &Bank) -> ScanResult<BTreeMap<Epoch, u64>> {
+    let withdraw_authority_list = withdraw_authority();
+    let clock = bank.clock();
+    let mut schedule = BTreeMap::new();
+    for (_pubkey, account) in load_stake_accounts(bank)?.iter() {
+        let Some(meta) = stake_state::from(account).and_then(|state| state.meta()) else {
+            continue;
+        };
+        if meta.lockup.epoch > clock.epoch
+            && meta.lockup.unix_timestamp <= clock.unix_timestamp
+            && !withdraw_authority_list.contains(&meta.authorized.withdrawer)
+        {
+            *schedule.entry(meta.lockup.epoch).or_default() += account.lamports();
+        }
+    }
+    Ok(schedule)
+}
+
+/// Counts the distinct future epochs at which the lockup of a stake account expires, as a
+/// measure of the complexity of the vesting schedule
+pub fn distinct_unlock_epochs(bank: &Bank) -> ScanResult<usize> {
+    let epoch = bank.epoch();
+    let unlock_epochs: HashSet<Epoch> = load_stake_accounts(bank)?
+        .iter()
+        .filter_map(|(_pubkey, account)| stake_state::lockup_from(account))
+        .map(|lockup| lockup.epoch)
+        .filter(|lockup_epoch| *lockup_epoch > epoch)
+        .collect();
+    Ok(unlock_epochs.len())
+}
+
+/// Removes the withdraw authority of stake account `account` from `stale_authorities`
+fn retain_stale_authorities(stale_authorities: &mut Vec<Pubkey>, account: &AccountSharedData) {
+    if stale_authorities.is_empty() {
+        return;
+    }
+    if let Some(meta) = stake_state::meta_from(account) {
+        stale_authorities.retain(|authority| *authority != meta.authorized.withdrawer);
+    }
+}
+
+fn is_non_circulating_stake_account(
+    account: &AccountSharedData,
+    clock: &Clock,
+    withdraw_authority_list: &[Pubkey],
+) -> bool {
+    stake_account_reason(account, clock, withdraw_authority_list).is_some()
+}
+
+/// Returns the change in a stake account's non-circulating contribution from `old_account` to
+/// `new_account`, e.g. to maintain a running total from account writes
+pub fn account_contribution_delta(
+    old_account: &AccountSharedData,
+    new_account: &AccountSharedData,
+    clock: &Clock,
+) -> i128 {
+    let withdraw_authority_list = withdraw_authority();
+    let contribution = |account: &AccountSharedData| {
+        if is_non_circulating_stake_account(account, clock, &withdraw_authority_list) {
+            i128::from(account.lamports())
+        } else {
+            0
+        }
+    };
+    contribution(new_account) - contribution(old_account)
+}
+
+/// Sums the lamports of the stake accounts controlled by each hardcoded withdraw authority
+pub fn withdraw_authority_lamports(
+    bank: &Bank,
+) -> Result<BTreeMap<Pubkey, u64>, NonCirculatingSupplyError> {
+    let withdraw_authority_list = withdraw_authority();
+    let stake_accounts = load_stake_accounts(bank)?;
+    aggregate_lamports_by_authority(stake_accounts.iter().filter_map(|(_pubkey, account)| {
+        let withdrawer = stake_state::from(account)?.meta()?.authorized.withdrawer;
+        withdraw_authority_list
+            .contains(&withdrawer)
+            .then_some((withdrawer, account.lamports()))
+    }))
+}
+
+/// Returns the total lamports and the stake accounts whose withdraw authority is `authority`,
+/// so the impact of rotating it can be assessed beforehand
+pub fn authority_controlled_stake(
+    bank: &Bank,
+    authority: &Pubkey,
+) -> ScanResult<(u64, Vec<Pubkey>)> {
+    let stake_accounts = load_stake_accounts(bank)?;
+    let mut total = 0;
+    let mut controlled_accounts = vec![];
+    for (pubkey, account) in stake_accounts.iter() {
+        let Some(meta) = stake_state::from(account).and_then(|state| state.meta()) else {
+            continue;
+        };
+        if meta.authorized.withdrawer == *authority {
+            total += account.lamports();
+            controlled_accounts.push(*pubkey);
+        }
+    }
+    Ok((total, controlled_accounts))
+}
+
+/// Returns `(authority, total_lamports, account_count)` for the stake accounts controlled by each
+/// hardcoded withdraw authority, in list order, including authorities controlling none
+pub fn authority_report(bank: &Bank) -> ScanResult<Vec<(Pubkey, u64, usize)>> {
+    let mut report: Vec<(Pubkey, u64, usize)> = withdraw_authority()
+        .into_iter()
+        .map(|authority| (authority, 0, 0))
+        .collect();
+    for (_pubkey, account) in load_stake_accounts(bank)?.iter() {
+        let Some(meta) = stake_state::from(account).and_then(|state| state.meta()) else {
+            continue;
+        };
+        if let Some((_authority, total, count)) = report
+            .iter_mut()
+            .find(|(authority, _total, _count)| *authority == meta.authorized.withdrawer)
+        {
+            *total += account.lamports();
+            *count += 1;
+        }
+    }
+    Ok(report)
+}
+
+/// Returns `(withdrawer, account_count, total_lamports)` for every distinct authorized withdrawer
+/// of the stake accounts, hardcoded or not, sorted by lamports descending
+pub fn withdrawer_census(bank: &Bank) -> ScanResult<Vec<(Pubkey, usize, u64)>> {
+    let mut census: BTreeMap<Pubkey, (usize, u64)> = BTreeMap::new();
+    for (_pubkey, account) in load_stake_accounts(bank)?.iter() {
+        let Some(meta) = stake_state::from(account).and_then(|state| state.meta()) else {
+            continue;
+        };
+        let (count, total) = census.entry(meta.authorized.withdrawer).or_default();
+        *count += 1;
+        *total = total.saturating_add(account.lamports());
+    }
+    let mut census: Vec<(Pubkey, usize, u64)> = census
+        .into_iter()
+        .map(|(withdrawer, (count, total))| (withdrawer, count, total))
+        .collect();
+    census.sort_by(|(_, _, a), (_, _, b)| b.cmp(a));
+    Ok(census)
+}
+
+/// Stake accounts controlled by a hardcoded withdraw authority, split by whether a lockup is also
+/// in force
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AuthorityLockupReport {
+    /// Accounts non-circulating only because of their withdraw authority
+    pub without_lockup: Vec<Pubkey>,
+    pub without_lockup_lamports: u64,
+    /// Accounts that are also locked up
+    pub with_lockup: Vec<Pubkey>,
+    pub with_lockup_lamports: u64,
+}
+
+/// Separates the stake controlled by hardcoded withdraw authorities that has no lockup in force,
+/// and so would be circulating under any other authority, from the stake that is also locked up
+pub fn authority_lockup_report(bank: &Bank) -> ScanResult<AuthorityLockupReport> {
+    let withdraw_authority_list = withdraw_authority();
+    let clock = bank.clock();
+    let mut report = AuthorityLockupReport::default();
+    for (pubkey, account) in load_stake_accounts(bank)?.iter() {
+        let Some(meta) = stake_state::from(account).and_then(|state| state.meta()) else {
+            continue;
+        };
+        if !withdraw_authority_list.contains(&meta.authorized.withdrawer) {
+            continue;
+        }
+        if meta.lockup.is_in_force(&clock, None) {
+            report.with_lockup.push(*pubkey);
+            report.with_lockup_lamports += account.lamports();
+        } else {
+            report.without_lockup.push(*pubkey);
+            report.without_lockup_lamports += account.lamports();
+        }
+    }
+    Ok(report)
+}
+
+/// Sums the lamports of the stake accounts that are non-circulating only because of a hardcoded
+/// withdraw authority, without a lockup in force, and so won't unlock with time
+pub fn permanently_non_circulating(bank: &Bank) -> ScanResult<u64> {
+    Ok(authority_lockup_report(bank)?.without_lockup_lamports)
+}
+
+/// Returns the `non_circulating_accounts()` entries owned by the stake program. The hardcoded list
+/// is meant for non-stake treasury accounts, so any such entry likely indicates a mistake.
+pub fn hardcoded_stake_accounts(bank: &Bank) -> Vec<Pubkey> {
+    non_circulating_accounts()
+        .into_iter()
+        .filter(|pubkey| {
+            bank.get_account(pubkey)
+                .is_some_and(|account| account.owner() == &stake::program::id())
+        })
+        .collect()
+}
+
+/// Returns the stake accounts that are non-circulating solely because of a lockup that its
+/// custodian could lift, as opposed to a lockup without a custodian or a hardcoded withdraw
+/// authority. These are candidates for reclassification once the custodian lifts the lockup.
+pub fn custodian_liftable_accounts(bank: &Bank) -> ScanResult<Vec<Pubkey>> {
+    let withdraw_authority_list = withdraw_authority();
+    let clock = bank.clock();
+    let mut accounts = vec![];
+    for (pubkey, account) in load_stake_accounts(bank)?.iter() {
+        let Some(meta) = stake_state::from(account).and_then(|state| state.meta()) else {
+            continue;
+        };
+        if meta.lockup.custodian != Pubkey::default()
+            && meta.lockup.is_in_force(&clock, None)
+            && !withdraw_authority_list.contains(&meta.authorized.withdrawer)
+        {
+            accounts.push(*pubkey);
+        }
+    }
+    Ok(accounts)
+}
+
+/// Returns the hardcoded withdraw authority that makes `stake_pubkey` non-circulating, if any
+pub fn locking_authority_of(bank: &Bank, stake_pubkey: &Pubkey) -> ScanResult<Option<Pubkey>> {
+    let withdraw_authority_list = withdraw_authority();
+    Ok(bank
+        .get_account(stake_pubkey)
+        .filter(|account| account.owner() == &stake::program::id())
+        .and_then(|account| stake_state::from(&account))
+        .and_then(|state| state.meta())
+        .map(|meta| meta.authorized.withdrawer)
+        .filter(|withdrawer| withdraw_authority_list.contains(withdrawer)))
+}
+
+/// Calculates the non-circulating supply over `pubkeys` only, fetching each account rather than
+/// scanning the stake program, e.g. to check the status of a specific allocation
+pub fn non_circulating_supply_for(
+    bank: &Bank,
+    pubkeys: &[Pubkey],
+) -> ScanResult<NonCirculatingSupply> {
+    let hardcoded_accounts = non_circulating_accounts();
+    let withdraw_authority_list = withdraw_authority();
+    let static_lists = StaticLists::new();
+    let clock = bank.clock();
+
+    let mut supply = NonCirculatingSupply {
+        lamports: 0,
+        accounts: vec![],
+        reasons: HashMap::new(),
+        balances: HashMap::new(),
+        burned_lamports: 0,
+        dust: None,
+        undecodable_accounts: vec![],
+        misowned_accounts: vec![],
+        panicked_decodes: 0,
+        activating_lamports: 0,
+        cooldown_lamports: 0,
+        reserve_lamports: 0,
+        static_lamports: 0,
+        dynamic_lamports: 0,
+        stale_authorities: vec![],
+        locked_rewards_lamports: 0,
+        lockups: HashMap::new(),
+        epoch: bank.epoch(),
+    };
+    for pubkey in pubkeys.iter() {
+        if hardcoded_accounts.contains(pubkey) {
+            supply.include_hardcoded_account(&static_lists, *pubkey, bank.get_balance(pubkey));
+            continue;
+        }
+        if supply.reasons.contains_key(pubkey) {
+            continue;
+        }
+        let Some(account) = bank
+            .get_account(pubkey)
+            .filter(|account| account.owner() == &stake::program::id())
+        else {
+            continue;
+        };
+        if let Some(reason) = stake_account_reason(&account, &clock, &withdraw_authority_list) {
+            supply.include_stake_account(
+                &static_lists,
+                *pubkey,
+                reason,
+                &account,
+                account.lamports(),
+            );
+        }
+    }
+    Ok(supply)
+}
+
+/// Classifies stake accounts already loaded and filtered by owner, e.g. by an existing
+/// account-loading pipeline, without scanning a bank. Accounts are trusted to be stake accounts;
+/// as a safeguard, any that isn't owned by the stake program is reported in `misowned_accounts`
+/// rather than decoded.
+///
+/// The result only covers `accounts`: hardcoded accounts are skipped, as a full scan counts them
+/// separately, and the classifiers for other programs don't apply. The stake classification,
+/// account rules, `min_lamports`, `exclude_rent_exempt_reserve` and `decode_failure_mode` of
+/// `config` are honored, with the reserves sized by `Rent::default()` unless `config.rent` is set.
+pub fn classify_prefiltered(
+    accounts: &[(Pubkey, AccountSharedData)],
+    clock: &Clock,
+    config: &NonCirculatingSupplyConfig,
+) -> ScanResult<NonCirculatingSupply> {
+    let mut hardcoded_accounts = non_circulating_accounts();
+    hardcoded_accounts.extend(config.additional_non_circulating_accounts.iter().copied());
+    let static_lists = StaticLists::new();
+    let stake_classifier = config.stake_classifier();
+    let rent = config.rent.clone().unwrap_or_default();
+
+    let mut supply = NonCirculatingSupply {
+        lamports: 0,
+        accounts: vec![],
+        reasons: HashMap::new(),
+        balances: HashMap::new(),
+        burned_lamports: 0,
+        dust: None,
+        undecodable_accounts: vec![],
+        misowned_accounts: vec![],
+        panicked_decodes: 0,
+        activating_lamports: 0,
+        cooldown_lamports: 0,
+        reserve_lamports: 0,
+        static_lamports: 0,
+        dynamic_lamports: 0,
+        stale_authorities: vec![],
+        locked_rewards_lamports: 0,
+        lockups: HashMap::new(),
+        epoch: clock.epoch,
+    };
+    for (pubkey, account) in accounts.iter() {
+        if account.owner() != &stake::program::id() {
+            supply.misowned_accounts.push(*pubkey);
+            continue;
+        }
+        if hardcoded_accounts.contains(pubkey)
+            || config
+                .min_lamports
+                .is_some_and(|min_lamports| account.lamports() < min_lamports)
+        {
+            continue;
+        }
+        let (reason, lamports) = match config.account_rule(pubkey, Some(account)) {
+            AccountRule::Excluded | AccountRule::Denied => continue,
+            AccountRule::Allowed => (
+                NonCirculatingReason::Custom(AccountRule::INCLUDED_REASON),
+                account.lamports(),
+            ),
+            AccountRule::Default => {
+                if stake_state::from(account).is_none() {
+                    match config.decode_failure_mode {
+                        DecodeFailureMode::Lenient => {
+                            supply.undecodable_accounts.push(*pubkey);
+                            continue;
+                        }
+                        DecodeFailureMode::Strict => {
+                            return Err(ScanError::Aborted(format!(
+                                "failed to deserialize stake account {pubkey}"
+                            )));
+                        }
+                    }
+                }
+                let Some(reason) = stake_classifier.classify(pubkey, account, clock) else {
+                    continue;
+                };
+                let lamports = if config.exclude_rent_exempt_reserve {
+                    account
+                        .lamports()
+                        .saturating_sub(rent.minimum_balance(account.data().len()))
+                } else {
+                    account.lamports()
+                };
+                (reason, lamports)
+            }
+        };
+        supply.include_stake_account(&static_lists, *pubkey, reason, account, lamports);
+    }
+    Ok(supply)
+}
+
+/// Updates `prev`, calculated against `bank`, for the rotation of hardcoded withdraw authority
+/// `old_authority` to `new_authority`. Only the stake accounts controlled by either authority are
+/// reclassified; all other accounts keep their classification from `prev`. No account index maps
+/// withdraw authorities to their stake accounts, so the stake program is still scanned, but the
+/// stake accounts are visited as the scan comes across them rather than collected.
+///
+/// Accounts folded into `prev.dust` stay there, with the dust adjusted for their change. The
+/// total is adjusted by the changes of the reclassified balances, saturating at zero, and so is
+/// only exact for a `prev` calculated without a `scale`.
+pub fn recompute_for_authority_change(
+    prev: &NonCirculatingSupply,
+    bank: &Bank,
+    old_authority: &Pubkey,
+    new_authority: &Pubkey,
+) -> ScanResult<NonCirculatingSupply> {
+    let withdraw_authority_list: Vec<Pubkey> = withdraw_authority()
+        .into_iter()
+        .map(|authority| {
+            if authority == *old_authority {
+                *new_authority
+            } else {
+                authority
+            }
+        })
+        .collect();
+    let clock = bank.clock();
+
+    let static_lists = StaticLists::new();
+
+    let mut accounts: HashSet<Pubkey> = prev.accounts.iter().copied().collect();
+    let mut reasons = prev.reasons.clone();
+    let mut balances = prev.balances.clone();
+    let mut lockups = prev.lockups.clone();
+    let mut lamports = prev.lamports;
+    let mut static_lamports = prev.static_lamports;
+    let mut dynamic_lamports = prev.dynamic_lamports;
+    let mut stale_authorities = withdraw_authority();
+    let mut dust = prev.dust;
+    scan_program_accounts(bank, &stake::program::id(), |pubkey, account| {
+        retain_stale_authorities(&mut stale_authorities, &account);
+        if reasons.get(pubkey) == Some(&NonCirculatingReason::Hardcoded) {
+            return;
+        }
+        let Some(meta) = stake_state::meta_from(&account) else {
+            return;
+        };
+        let withdrawer = meta.authorized.withdrawer;
+        if withdrawer != *old_authority && withdrawer != *new_authority {
+            return;
+        }
+        // Lamports counted for the withdraw authority are attributed to the compiled-in list
+        // only while the authority is on it
+        let is_static = static_lists.is_static(
+            pubkey,
+            NonCirculatingReason::WithdrawAuthority,
+            Some(&withdrawer),
+        );
+        let authority_lamports = if is_static == Some(true) {
+            &mut static_lamports
+        } else {
+            &mut dynamic_lamports
+        };
+        let previous = balances.get(pubkey).copied();
+        if reasons.get(pubkey) == Some(&NonCirculatingReason::WithdrawAuthority) {
+            *authority_lamports = authority_lamports.saturating_sub(previous.unwrap_or_default());
+        }
+        // Counted, but folded into the dust rather than listed
+        let folded_dust = dust
+            .as_mut()
+            .filter(|_| previous.is_some() && !accounts.contains(pubkey));
+        lamports = lamports.saturating_sub(previous.unwrap_or_default());
+        match stake_account_reason(&account, &clock, &withdraw_authority_list) {
+            Some(reason) => {
+                reasons.insert(*pubkey, reason);
+                if reason == NonCirculatingReason::Lockup {
+                    lockups.insert(*pubkey, meta.lockup);
+                } else {
+                    lockups.remove(pubkey);
+                }
+                balances.insert(*pubkey, account.lamports());
+                lamports = lamports.saturating_add(account.lamports());
+                if reason == NonCirculatingReason::WithdrawAuthority {
+                    *authority_lamports += account.lamports();
+                }
+                match folded_dust {
+                    Some(dust) => {
+                        dust.lamports = dust
+                            .lamports
+                            .saturating_sub(previous.unwrap_or_default())
+                            .saturating_add(account.lamports());
+                    }
+                    None => {
+                        accounts.insert(*pubkey);
+                    }
+                }
+            }
+            None => {
+                accounts.remove(pubkey);
+                reasons.remove(pubkey);
+                lockups.remove(pubkey);
+                balances.remove(pubkey);
+                if let Some(dust) = folded_dust {
+                    dust.lamports = dust.lamports.saturating_sub(previous.unwrap_or_default());
+                    dust.count = dust.count.saturating_sub(1);
+                }
+            }
+        }
+    })?;
+
+    Ok(NonCirculatingSupply {
+        lamports,
+        accounts: accounts.into_iter().collect(),
+        reasons,
+        balances,
+        burned_lamports: prev.burned_lamports,
+        dust,
+        undecodable_accounts: prev.undecodable_accounts.clone(),
+        misowned_accounts: prev.misowned_accounts.clone(),
+        panicked_decodes: prev.panicked_decodes,
+        activating_lamports: prev.activating_lamports,
+        cooldown_lamports: prev.cooldown_lamports,
+        reserve_lamports: prev.reserve_lamports,
+        static_lamports,
+        dynamic_lamports,
+        stale_authorities,
+        locked_rewards_lamports: prev.locked_rewards_lamports,
+        lockups,
+        epoch: bank.epoch(),
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SupplyChangeDirection {
+    Increase,
+    Decrease,
+}
+
+/// A change of the non-circulating supply beyond the tolerated amount
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SupplyAlarm {
+    pub direction: SupplyChangeDirection,
+    pub delta_lamports: u64,
+    /// Magnitude of the change relative to the baseline in basis points, saturating at
+    /// `u64::MAX` for a zero baseline
+    pub change_bps: u64,
+}
+
+/// Returns an alarm if `current` differs from `baseline` by more than `tolerance_bps` basis
+/// points of `baseline`
+pub fn supply_alarm(baseline: u64, current: u64, tolerance_bps: u64) -> Option<SupplyAlarm> {
+    let (direction, delta_lamports) = if current >= baseline {
+        (SupplyChangeDirection::Increase, current - baseline)
+    } else {
+        (SupplyChangeDirection::Decrease, baseline - current)
+    };
+    let scaled_delta = u128::from(delta_lamports) * 10_000;
+    if scaled_delta <= u128::from(tolerance_bps) * u128::from(baseline) {
+        return None;
+    }
+    let change_bps = scaled_delta
+        .checked_div(u128::from(baseline))
+        .map_or(u64::MAX, |change_bps| {
+            u64::try_from(change_bps).unwrap_or(u64::MAX)
+        });
+    Some(SupplyAlarm {
+        direction,
+        delta_lamports,
+        change_bps,
+    })
+}
+
+/// Returns `(added, removed)`: the accounts counted as non-circulating in `new` but not in `old`,
+/// and those counted in `old` but not in `new`, each sorted by pubkey. Balance changes of
+/// accounts counted in both are ignored.
+pub fn accounts_diff(
+    old: &NonCirculatingSupply,
+    new: &NonCirculatingSupply,
+) -> (Vec<Pubkey>, Vec<Pubkey>) {
+    let mut added: Vec<Pubkey> = new
+        .reasons
+        .keys()
+        .filter(|pubkey| !old.reasons.contains_key(*pubkey))
+        .copied()
+        .collect();
+    let mut removed: Vec<Pubkey> = old
+        .reasons
+        .keys()
+        .filter(|pubkey| !new.reasons.contains_key(*pubkey))
+        .copied()
+        .collect();
+    added.sort_unstable();
+    removed.sort_unstable();
+    (added, removed)
+}
+
+/// Differences between two supplies, as computed by `supply_delta()`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SupplyDelta {
+    /// Change of the non-circulating total
+    pub lamports_delta: i128,
+    /// Accounts counted in the new supply only, with their counted balances
+    pub added: Vec<(Pubkey, u64)>,
+    /// Accounts counted in the old supply only, with their counted balances
+    pub removed: Vec<(Pubkey, u64)>,
+    /// Accounts counted in both whose counted balance changed by at least the threshold, with
+    /// the change
+    pub changed: Vec<(Pubkey, i128)>,
+}
+
+/// Returns the per-account differences from `old` to `new`, each list sorted by pubkey. Accounts
+/// counted in both whose counted balance changed by less than `min_delta_lamports` are left out
+/// of `changed`, while added and removed accounts are always reported.
+pub fn supply_delta(
+    old: &NonCirculatingSupply,
+    new: &NonCirculatingSupply,
+    min_delta_lamports: u64,
+) -> SupplyDelta {
+    let balance = |supply: &NonCirculatingSupply, pubkey: &Pubkey| {
+        supply.balances.get(pubkey).copied().unwrap_or_default()
+    };
+    let (added, removed) = accounts_diff(old, new);
+    let mut changed: Vec<(Pubkey, i128)> = new
+        .reasons
+        .keys()
+        .filter(|pubkey| old.reasons.contains_key(*pubkey))
+        .filter_map(|pubkey| {
+            let old_balance = balance(old, pubkey);
+            let new_balance = balance(new, pubkey);
+            (old_balance != new_balance && old_balance.abs_diff(new_balance) >= min_delta_lamports)
+                .then(|| (*pubkey, i128::from(new_balance) - i128::from(old_balance)))
+        })
+        .collect();
+    changed.sort_unstable();
+    SupplyDelta {
+        lamports_delta: i128::from(new.lamports) - i128::from(old.lamports),
+        added: added
+            .into_iter()
+            .map(|pubkey| (pubkey, balance(new, &pubkey)))
+            .collect(),
+        removed: removed
+            .into_iter()
+            .map(|pubkey| (pubkey, balance(old, &pubkey)))
+            .collect(),
+        changed,
+    }
+}
+
+/// Returns the accounts counted in `supply`, sorted by pubkey, that no longer qualify as
+/// non-circulating on `bank`, e.g. because their lockup has expired since it was calculated.
+/// Accounts counted for a `Cooldown` or `Custom` reason depend on the configuration the supply
+/// was calculated with, and are never reported.
+pub fn revalidate(supply: &NonCirculatingSupply, bank: &Bank) -> ScanResult<Vec<Pubkey>> {
+    let hardcoded_accounts = non_circulating_accounts();
+    let withdraw_authority_list = withdraw_authority();
+    let clock = bank.clock();
+    let mut stale: Vec<Pubkey> = supply
+        .reasons
+        .iter()
+        .filter(|(pubkey, reason)| match reason {
+            NonCirculatingReason::Hardcoded => !hardcoded_accounts.contains(pubkey),
+            NonCirculatingReason::Lockup | NonCirculatingReason::WithdrawAuthority => {
+                bank.get_account(pubkey).is_none_or(|account| {
+                    !is_non_circulating_stake_account(&account, &clock, &withdraw_authority_list)
+                })
+            }
+            NonCirculatingReason::Cooldown | NonCirculatingReason::Custom(_) => false,
+        })
+        .map(|(pubkey, _reason)| *pubkey)
+        .collect();
+    stale.sort_unstable();
+    Ok(stale)
+}
+
+/// Returns the share of stake program lamports that is non-circulating, or 0.0 if there are
+/// none
+pub fn locked_stake_ratio(bank: &Bank) -> ScanResult<f64> {
+    let withdraw_authority_list = withdraw_authority();
+    let clock = bank.clock();
+    let mut locked_stake = 0u64;
+    let mut total_stake = 0u64;
+    for (_pubkey, account) in load_stake_accounts(bank)?.iter() {
+        total_stake += account.lamports();
+        if is_non_circulating_stake_account(account, &clock, &withdraw_authority_list) {
+            locked_stake += account.lamports();
+        }
+    }
+    if total_stake == 0 {
+        return Ok(0.0);
+    }
+    Ok(locked_stake as f64 / total_stake as f64)
+}
+
+/// Sums the lamports of the non-circulating stake accounts delegated to `voter`
+pub fn non_circulating_delegated_to(bank: &Bank, voter: &Pubkey) -> ScanResult<u64> {
+    let withdraw_authority_list = withdraw_authority();
+    let clock = bank.clock();
+    Ok(load_stake_accounts(bank)?
+        .iter()
+        .filter(|(_pubkey, account)| {
+            stake_state::delegation_from(account)
+                .is_some_and(|delegation| delegation.voter_pubkey == *voter)
+                && is_non_circulating_stake_account(account, &clock, &withdraw_authority_list)
+        })
+        .map(|(_pubkey, account)| account.lamports())
+        .sum())
+}
+
+/// Returns `(lockup, authority)`: the lamports of the non-circulating stake accounts that are
+/// time-locked and of those that are only controlled by a hardcoded withdraw authority. Accounts
+/// that are both are attributed to `lockup`.
+pub fn lockup_vs_authority_ratio(bank: &Bank) -> ScanResult<(u64, u64)> {
+    let withdraw_authority_list = withdraw_authority();
+    let clock = bank.clock();
+    let mut lockup = 0;
+    let mut authority = 0;
+    for (_pubkey, account) in load_stake_accounts(bank)?.iter() {
+        match stake_account_reason(account, &clock, &withdraw_authority_list) {
+            Some(NonCirculatingReason::Lockup) => lockup += account.lamports(),
+            Some(NonCirculatingReason::WithdrawAuthority) => authority += account.lamports(),
+            _ => {}
+        }
+    }
+    Ok((lockup, authority))
+}
+
+/// Returns the lamport-weighted average number of epochs remaining until the epoch lockups of
+/// the stake accounts expire, over the accounts whose epoch lockup is in force, or 0.0 if there
+/// are none. Unix timestamp lockups aren't measured.
+pub fn average_lockup_remaining(bank: &Bank) -> ScanResult<f64> {
+    let epoch = bank.epoch();
+    let mut weighted_epochs = 0u128;
+    let mut locked_lamports = 0u128;
+    for (_pubkey, account) in load_stake_accounts(bank)?.iter() {
+        let Some(lockup) = stake_state::lockup_from(account) else {
+            continue;
+        };
+        if lockup.epoch > epoch {
+            let lamports = u128::from(account.lamports());
+            weighted_epochs += lamports * u128::from(lockup.epoch - epoch);
+            locked_lamports += lamports;
+        }
+    }
+    if locked_lamports == 0 {
+        return Ok(0.0);
+    }
+    Ok(weighted_epochs as f64 / locked_lamports as f64)
+}
+
+/// Returns the bank's capitalization minus the circulating and non-circulating lamports summed
+/// over a scan of all accounts, which is zero unless the two have diverged
+pub fn supply_reconciliation_gap(bank: &Bank) -> ScanResult<i128> {
+    let supply = calculate_non_circulating_supply(bank)?;
+    let mut circulating = 0u64;
+    bank.scan_all_accounts(
+        |entry| {
+            if let Some((pubkey, account, _slot)) = entry {
+                let non_circulating = supply.balances.get(pubkey).copied().unwrap_or_default();
+                circulating += account.lamports().saturating_sub(non_circulating);
+            }
+        },
+        false,
+    )?;
+    Ok(i128::from(bank.capitalization()) - i128::from(circulating) - i128::from(supply.lamports))
+}
+
+/// Returns the circulating share of the total supply as a percentage rounded to `decimals`
+/// decimal places, or 0.0 if there is no supply
+pub fn circulating_share_pct(bank: &Bank, decimals: u8) -> ScanResult<f64> {
+    let supply = calculate_non_circulating_supply(bank)?;
+    let total_supply = supply.total_supply(bank.capitalization());
+    if total_supply == 0 {
+        return Ok(0.0);
+    }
+    let circulating = total_supply.saturating_sub(supply.lamports);
+    let pct = circulating as f64 / total_supply as f64 * 100.0;
+    let scale = 10f64.powi(i32::from(decimals));
+    Ok((pct * scale).round() / scale)
+}
+
+/// Returns the capitalization of `bank` less both its non-circulating lamports and the balances
+/// of `burn_addresses`, whose lamports can never be spent again. Burn addresses already counted
+/// as non-circulating are subtracted once. Aborts if the subtractions underflow, as the balances
+/// are then inconsistent with the capitalization.
+pub fn effective_circulating_supply(bank: &Bank, burn_addresses: &[Pubkey]) -> ScanResult<u64> {
+    let supply = calculate_non_circulating_supply(bank)?;
+    let burn_addresses: HashSet<&Pubkey> = burn_addresses
+        .iter()
+        .filter(|pubkey| !supply.balances.contains_key(*pubkey))
+        .collect();
+    let mut burned_lamports = 0u64;
+    for pubkey in burn_addresses {
+        burned_lamports = burned_lamports
+            .checked_add(bank.get_balance(pubkey))
+            .ok_or_else(|| ScanError::Aborted("burned lamports overflow u64".to_string()))?;
+    }
+    let capitalization = bank.capitalization();
+    capitalization
+        .checked_sub(supply.lamports)
+        .and_then(|circulating| circulating.checked_sub(burned_lamports))
+        .ok_or_else(|| {
+            ScanError::Aborted(format!(
+                "non-circulating lamports {} and burned lamports {burned_lamports} exceed the \
+                 capitalization {capitalization}",
+                supply.lamports
+            ))
+        })
+}
+
+/// Sums the balances of the hardcoded non-circulating accounts, without scanning any stake
+/// accounts
+pub fn hardcoded_non_circulating_lamports(bank: &Bank) -> u64 {
+    non_circulating_accounts()
+        .iter()
+        .map(|pubkey| bank.get_balance(pubkey))
+        .sum()
+}
+
+/// Returns whether the non-circulating accounts of `bank`, or their balances, differ from the
+/// ones `prev_checksum` was computed over by `NonCirculatingSupply::checksum()`
+pub fn has_supply_changed(bank: &Bank, prev_checksum: [u8; 32]) -> ScanResult<bool> {
+    Ok(calculate_non_circulating_supply(bank)?.checksum() != prev_checksum)
+}
+
+/// Prefix of Merkle leaves, as in `solana-merkle-tree`, so that a leaf can't be passed off as an
+/// intermediate node
+const MERKLE_LEAF_PREFIX: &[u8] = &[0];
+
+/// Hashes the non-circulating total, epoch, slot of the bank it was calculated at, and accounts
+/// checksum of `supply` into a single leaf, to be committed to in a Merkle tree of supply reports
+pub fn supply_merkle_leaf(supply: &NonCirculatingSupply, bank_slot: Slot) -> [u8; 32] {
+    let mut hasher = Hasher::default();
+    hasher.hash(MERKLE_LEAF_PREFIX);
+    hasher.hash(&supply.lamports.to_le_bytes());
+    hasher.hash(&supply.epoch.to_le_bytes());
+    hasher.hash(&bank_slot.to_le_bytes());
+    hasher.hash(&supply.checksum());
+    hasher.result().to_bytes()
+}
+
+/// Returns `(non_circulating, circulating_stake)`: the number of non-circulating accounts,
+/// hardcoded ones included, and of circulating stake program accounts
+pub fn account_count_breakdown(bank: &Bank) -> ScanResult<(usize, usize)> {
+    let hardcoded_accounts = non_circulating_accounts();
+    let withdraw_authority_list = withdraw_authority();
+    let clock = bank.clock();
+    let mut non_circulating = hardcoded_accounts.len();
+    let mut circulating_stake = 0;
+    for (pubkey, account) in load_stake_accounts(bank)?.iter() {
+        if hardcoded_accounts.contains(pubkey) {
+            continue;
+        }
+        if is_non_circulating_stake_account(account, &clock, &withdraw_authority_list) {
+            non_circulating += 1;
+        } else {
+            circulating_stake += 1;
+        }
+    }
+    Ok((non_circulating, circulating_stake))
+}
+
+/// Splits the stake program accounts into `(non_circulating, circulating)` in a single scan.
+/// Hardcoded stake accounts are non-circulating.
+pub fn partition_stake_accounts(bank: &Bank) -> ScanResult<(Vec<Pubkey>, Vec<Pubkey>)> {
+    let hardcoded_accounts = non_circulating_accounts();
+    let withdraw_authority_list = withdraw_authority();
+    let clock = bank.clock();
+    let mut non_circulating = vec![];
+    let mut circulating = vec![];
+    for (pubkey, account) in load_stake_accounts(bank)?.iter() {
+        if hardcoded_accounts.contains(pubkey)
+            || is_non_circulating_stake_account(account, &clock, &withdraw_authority_list)
+        {
+            non_circulating.push(*pubkey);
+        } else {
+            circulating.push(*pubkey);
+        }
+    }
+    Ok((non_circulating, circulating))
+}
+
+/// Returns the `k` non-circulating accounts with the largest balances, sorted descending. Only
+/// the `k` largest seen so far are kept while scanning, rather than all non-circulating accounts.
+pub fn top_k_non_circulating(bank: &Bank, k: usize) -> ScanResult<Vec<(Pubkey, u64)>> {
+    let hardcoded_accounts = non_circulating_accounts();
+    let withdraw_authority_list = withdraw_authority();
+    let clock = bank.clock();
+    // Min-heap of the largest balances seen so far
+    let mut top: BinaryHeap<Reverse<(u64, Pubkey)>> = BinaryHeap::new();
+    let mut offer = |pubkey: Pubkey, lamports: u64| {
+        top.push(Reverse((lamports, pubkey)));
+        if top.len() > k {
+            top.pop();
+        }
+    };
+    for pubkey in hardcoded_accounts.iter() {
+        offer(*pubkey, bank.get_balance(pubkey));
+    }
+    // Each account is offered as the scan comes across it, rather than collecting all stake
+    // accounts first
+    scan_program_accounts(bank, &stake::program::id(), |pubkey, account| {
+        if !hardcoded_accounts.contains(pubkey)
+            && is_non_circulating_stake_account(&account, &clock, &withdraw_authority_list)
+        {
+            offer(*pubkey, account.lamports());
+        }
+    })?;
+    // Ascending order of `Reverse` is descending order of the balances
+    Ok(top
+        .into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((lamports, pubkey))| (pubkey, lamports))
+        .collect())
+}
+
+/// Counts the stake program accounts in each `StakeStateV2` variant. Accounts whose data fails
+/// to deserialize aren't counted.
+pub fn stake_state_breakdown(bank: &Bank) -> ScanResult<HashMap<&'static str, usize>> {
+    let mut breakdown: HashMap<&'static str, usize> =
+        ["Uninitialized", "Initialized", "Stake", "RewardsPool"]
+            .into_iter()
+            .map(|variant| (variant, 0))
+            .collect();
+    for (_pubkey, account) in load_stake_accounts(bank)?.iter() {
+        let variant = match stake_state::from(account) {
+            Some(StakeStateV2::Uninitialized) => "Uninitialized",
+            Some(StakeStateV2::Initialized(_meta)) => "Initialized",
+            Some(StakeStateV2::Stake(_meta, _stake, _stake_flags)) => "Stake",
+            Some(StakeStateV2::RewardsPool) => "RewardsPool",
+            None => continue,
+        };
+        *breakdown.entry(variant).or_default() += 1;
+    }
+    Ok(breakdown)
+}
+
+fn aggregate_lamports_by_authority(
+    balances: impl IntoIterator<Item = (Pubkey, u64)>,
+) -> Result<BTreeMap<Pubkey, u64>, NonCirculatingSupplyError> {
+    let mut totals = BTreeMap::new();
+    for (authority, lamports) in balances {
+        let total: &mut u64 = totals.entry(authority).or_default();
+        *total = total.checked_add(lamports).ok_or(
+            NonCirculatingSupplyError::AuthorityLamportsOverflow(authority),
+        )?;
+    }
+    Ok(totals)
+}
+
+fn load_stake_accounts(bank: &Bank) -> ScanResult<Vec<(Pubkey, AccountSharedData)>> {
+    load_program_accounts(bank, &stake::program::id())
+}
+
+fn load_program_accounts(
+    bank: &Bank,
+    program_id: &Pubkey,
+) -> ScanResult<Vec<(Pubkey, AccountSharedData)>> {
+    load_program_accounts_at(bank, &bank.ancestors, bank.bank_id(), program_id, || {})
+}
+
+/// Loads the accounts of `program_id` visible from `ancestors`, which need not be `bank`'s own,
+/// calling `on_loaded` for each one as the scan comes across it
+fn load_program_accounts_at(
+    bank: &Bank,
+    ancestors: &Ancestors,
+    bank_id: BankId,
+    program_id: &Pubkey,
+    on_loaded: impl Fn(),
+) -> ScanResult<Vec<(Pubkey, AccountSharedData)>> {
+    let config = &ScanConfig::default();
+    if bank
+        .rc
+        .accounts
+        .accounts_db
+        .account_indexes
+        .contains(&AccountIndex::ProgramId)
+    {
+        bank.rc.accounts.load_by_index_key_with_filter(
+            ancestors,
+            bank_id,
+            &IndexKey::ProgramId(*program_id),
+            // The program-id account index checks for Account owner on inclusion. However, due to
+            // the current AccountsDb implementation, an account may remain in storage as a
+            // zero-lamport Account::Default() after being wiped and reinitialized in later
+            // updates. We include the redundant filter here to avoid returning these accounts.
+            |account| {
+                let is_program_account = account.owner() == program_id;
+                if is_program_account {
+                    on_loaded();
+                }
+                is_program_account
+            },
+            config,
+            None,
+        )
+    } else {
+        bank.rc.accounts.load_by_program_with_filter(
+            ancestors,
+            bank_id,
+            program_id,
+            |_account| {
+                on_loaded();
+                true
+            },
+            config,
+        )
+    }
+}
+
+/// Passes each account of `program_id` visible from `bank` to `scan_func` as the scan comes across
+/// it, through the program id account index if enabled, rather than collecting them first
+fn scan_program_accounts(
+    bank: &Bank,
+    program_id: &Pubkey,
+    mut scan_func: impl FnMut(&Pubkey, AccountSharedData),
+) -> ScanResult<()> {
+    let accounts_db = &bank.rc.accounts.accounts_db;
+    let config = &ScanConfig::default();
+    let scan_func = |entry: Option<(&Pubkey, AccountSharedData, Slot)>| {
+        // Accounts without lamports don't exist, and the program id index may still return
+        // accounts reassigned since, as in `load_program_accounts_at()`
+        if let Some((pubkey, account, _slot)) = entry.filter(|(_pubkey, account, _slot)| {
+            account.lamports() > 0 && account.owner() == program_id
+        }) {
+            scan_func(pubkey, account);
+        }
+    };
+    if accounts_db
+        .account_indexes
+        .contains(&AccountIndex::ProgramId)
+    {
+        accounts_db.index_scan_accounts(
+            &bank.ancestors,
+            bank.bank_id(),
+            IndexKey::ProgramId(*program_id),
+            scan_func,
+            config,
+        )?;
+    } else {
+        accounts_db.scan_accounts(&bank.ancestors, bank.bank_id(), scan_func, config)?;
+    }
+    Ok(())
+}
+
+/// Scans the accounts of `program_id` visible from `ancestors` again without the program id
+/// account index, checking that the result matches `program_accounts`
+fn verify_double_scan(
+    bank: &Bank,
+    ancestors: &Ancestors,
+    bank_id: BankId,
+    program_id: &Pubkey,
+    program_accounts: &[(Pubkey, AccountSharedData)],
+) -> ScanResult<Result<(), DivergenceError>> {
+    let mut first: Vec<&(Pubkey, AccountSharedData)> = program_accounts.iter().collect();
+    first.sort_unstable_by_key(|(pubkey, _account)| *pubkey);
+    let mut second =
+        bank.rc
+            .accounts
+            .load_by_program(ancestors, bank_id, program_id, &ScanConfig::default())?;
+    second.sort_unstable_by_key(|(pubkey, _account)| *pubkey);
+    let diverging = first
+        .iter()
+        .zip(second.iter())
+        .find(|(first, second)| first.0 != second.0 || first.1 != second.1)
+        .map(|(first, second)| first.0.min(second.0))
+        .or_else(|| match first.len().cmp(&second.len()) {
+            Ordering::Less => Some(second[first.len()].0),
+            Ordering::Greater => Some(first[second.len()].0),
+            Ordering::Equal => None,
+        });
+    Ok(match diverging {
+        Some(pubkey) => Err(DivergenceError {
+            program_id: *program_id,
+            pubkey,
+        }),
+        None => Ok(()),
+    })
+}
+
+/// Entry of `non_circulating_accounts()` that
+/// `NonCirculatingSupplyConfig::protocol_fees_as_circulating` counts as circulating
+pub const PROTOCOL_FEE_ACCOUNT: Pubkey = solana_pubkey::pubkey!("11111111111111111111111111111112");
+
+// Mainnet-beta accounts that should be considered non-circulating
+#[cfg(feature = "mainnet-lists")]
+pub fn non_circulating_accounts() -> Vec<Pubkey> {
+    [
+        solana_pubkey::pubkey!("11111111111111111111111111111112"),
+    ]
+    .into()
+}
+
+#[cfg(not(feature = "mainnet-lists"))]
+pub fn non_circulating_accounts() -> Vec<Pubkey> {
+    vec![]
+}
+
+// Withdraw authority for autostaked accounts on mainnet-beta
+#[cfg(feature = "mainnet-lists")]
+pub fn withdraw_authority() -> Vec<Pubkey> {
+    [
+        solana_pubkey::pubkey!("8CUUMKYNGxdgYio5CLHRHyzMEhhVRMcqefgE6dLqnVRK"),
+        solana_pubkey::pubkey!("3FFaheyqtyAXZSYxDzsr5CVKvJuvZD1WE1VEsBtDbRqB"),
+        solana_pubkey::pubkey!("FdGYQdiRky8NZzN9wZtczTBcWLYYRXrJ3LMDhqDPn5rM"),
+        solana_pubkey::pubkey!("4e6KwQpyzGQPfgVr5Jn3g5jLjbXB4pKPa2jRLohEb1QA"),
+        solana_pubkey::pubkey!("FjiEiVKyMGzSLpqoB27QypukUfyWHrwzPcGNtopzZVdh"),
+        solana_pubkey::pubkey!("DwbVjia1mYeSGoJipzhaf4L5hfer2DJ1Ys681VzQm5YY"),
+        solana_pubkey::pubkey!("GeMGyvsTEsANVvcT5cme65Xq5MVU8fVVzMQ13KAZFNS2"),
+        solana_pubkey::pubkey!("Bj3aQ2oFnZYfNR1njzRjmWizzuhvfcYLckh76cqsbuBM"),
+        solana_pubkey::pubkey!("4ZJhPQAgUseCsWhKvJLTmmRRUV74fdoTpQLNfKoekbPY"),
+        solana_pubkey::pubkey!("HXdYQ5gixrY2H6Y9gqsD8kPM2JQKSaRiohDQtLbZkRWE"),
+    ]
+    .into()
+}
+
+#[cfg(not(feature = "mainnet-lists"))]
+pub fn withdraw_authority() -> Vec<Pubkey> {
+    vec![]
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{bank::BankTestConfig, genesis_utils::genesis_sysvar_and_builtin_program_lamports},
+        rand::{Rng, SeedableRng},
+        rand_chacha::ChaChaRng,
+        solana_account::{Account, AccountSharedData, WritableAccount},
+        solana_accounts_db::{
+            accounts_db::{AccountsDbConfig, ACCOUNTS_DB_CONFIG_FOR_TESTING},
+            accounts_index::AccountSecondaryIndexes,
+        },
+        solana_cluster_type::ClusterType,
+        solana_genesis_config::{create_genesis_config, GenesisConfig},
+        solana_stake_interface::{
+            stake_flags::StakeFlags,
+            state::{Authorized, Delegation, Lockup, Meta, Stake},
+        },
+        std::{collections::BTreeMap, sync::Arc},
+    };
+
+    fn new_from_parent(parent: Arc<Bank>) -> Bank {
+        let slot = parent.slot() + 1;
+        let collector_id = Pubkey::default();
+        Bank::new_from_parent(parent, &collector_id, slot)
+    }
+
+    pub(super) fn new_stake_account(lamports: u64, withdrawer: &Pubkey, lockup: Lockup) -> Account {
+        let meta = Meta {
+            authorized: Authorized {
+                staker: *withdrawer,
+                withdrawer: *withdrawer,
+            },
+            lockup,
+            ..Meta::default()
+        };
+        Account::new_data_with_space(
+            lamports,
+            &StakeStateV2::Initialized(meta),
+            StakeStateV2::size_of(),
+            &stake::program::id(),
+        )
+        .unwrap()
+    }
+
+    fn new_delegated_stake_account(
+        lamports: u64,
+        withdrawer: &Pubkey,
+        lockup: Lockup,
+        delegation: Delegation,
+    ) -> Account {
+        let meta = Meta {
+            authorized: Authorized {
+                staker: *withdrawer,
+                withdrawer: *withdrawer,
+            },
+            lockup,
+            ..Meta::default()
+        };
+        let stake = Stake {
+            delegation,
+            credits_observed: 0,
+        };
+        Account::new_data_with_space(
+            lamports,
+            &StakeStateV2::Stake(meta, stake, StakeFlags::empty()),
+            StakeStateV2::size_of(),
+            &stake::program::id(),
+        )
+        .unwrap()
+    }
+
+    /// Generates `num_circulating` system accounts, the hardcoded non-circulating accounts and
+    /// `num_locked_stake` stake accounts locked until epoch 1, with pubkeys and balances derived
+    /// from `seed` so that a failing case can be replayed
+    fn create_random_genesis_accounts(
+        seed: u64,
+        num_circulating: usize,
+        num_locked_stake: usize,
+    ) -> BTreeMap<Pubkey, Account> {
+        let mut rng = ChaChaRng::seed_from_u64(seed);
+        let mut accounts = BTreeMap::new();
+        for _ in 0..num_circulating {
+            accounts.insert(
+                Pubkey::new_from_array(rng.gen()),
+                Account::new(rng.gen_range(1..1_000), 0, &Pubkey::default()),
+            );
+        }
+        for key in non_circulating_accounts() {
+            accounts.insert(
+                key,
+                Account::new(rng.gen_range(1..1_000), 0, &Pubkey::default()),
+            );
+        }
+        let lockup = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        for _ in 0..num_locked_stake {
+            let withdrawer = Pubkey::new_from_array(rng.gen());
+            accounts.insert(
+                Pubkey::new_from_array(rng.gen()),
+                new_stake_account(rng.gen_range(1..1_000), &withdrawer, lockup),
+            );
+        }
+        accounts
+    }
+
+    /// Asserts that no account became non-circulating and that circulating supply didn't
+    /// decrease from `parent` to `child`, which holds as long as balances are unchanged since
+    /// lockups only ever unlock
+    fn assert_supply_monotonic_circulating(parent: &Bank, child: &Bank) {
+        let parent_supply = calculate_non_circulating_supply(parent).unwrap();
+        let child_supply = calculate_non_circulating_supply(child).unwrap();
+        for pubkey in child_supply.reasons.keys() {
+            assert!(
+                parent_supply.reasons.contains_key(pubkey),
+                "{pubkey} became non-circulating between slots {} and {}",
+                parent.slot(),
+                child.slot(),
+            );
+        }
+        let parent_circulating = parent.capitalization() - parent_supply.lamports;
+        let child_circulating = child.capitalization() - child_supply.lamports;
+        assert!(
+            child_circulating >= parent_circulating,
+            "circulating supply decreased from {parent_circulating} at slot {} to \
+             {child_circulating} at slot {}",
+            parent.slot(),
+            child.slot(),
+        );
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply() {
+        let mut accounts: BTreeMap<Pubkey, Account> = BTreeMap::new();
+        let balance = 10;
+        let num_genesis_accounts = 10;
+        for _ in 0..num_genesis_accounts {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                Account::new(balance, 0, &Pubkey::default()),
+            );
+        }
+        let non_circulating_accounts = non_circulating_accounts();
+        let num_non_circulating_accounts = non_circulating_accounts.len() as u64;
+        for key in non_circulating_accounts.clone() {
+            accounts.insert(key, Account::new(balance, 0, &Pubkey::default()));
+        }
+
+        let num_stake_accounts = 3;
+        for _ in 0..num_stake_accounts {
+            let pubkey = solana_pubkey::new_rand();
+            let meta = Meta {
+                authorized: Authorized::auto(&pubkey),
+                lockup: Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+                ..Meta::default()
+            };
+            let stake_account = Account::new_data_with_space(
+                balance,
+                &StakeStateV2::Initialized(meta),
+                StakeStateV2::size_of(),
+                &stake::program::id(),
+            )
+            .unwrap();
+            accounts.insert(pubkey, stake_account);
+        }
+
+        let slots_per_epoch = 32;
+        let genesis_config = GenesisConfig {
+            accounts,
+            epoch_schedule: EpochSchedule::new(slots_per_epoch),
+            cluster_type: ClusterType::MainnetBeta,
+            ..GenesisConfig::default()
+        };
+        let mut bank = Arc::new(Bank::new_for_tests(&genesis_config));
+        assert_eq!(
+            bank.capitalization(),
+            (num_genesis_accounts + num_non_circulating_accounts + num_stake_accounts) * balance
+                + genesis_sysvar_and_builtin_program_lamports(),
+        );
+
+        let non_circulating_supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(
+            non_circulating_supply.lamports,
+            (num_non_circulating_accounts + num_stake_accounts) * balance
+        );
+        assert_eq!(
+            non_circulating_supply.accounts.len(),
+            num_non_circulating_accounts as usize + num_stake_accounts as usize
+        );
+
+        bank = Arc::new(new_from_parent(bank));
+        let new_balance = 11;
+        for key in non_circulating_accounts {
+            bank.store_account(
+                &key,
+                &AccountSharedData::new(new_balance, 0, &Pubkey::default()),
+            );
+        }
+        let non_circulating_supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(
+            non_circulating_supply.lamports,
+            (num_non_circulating_accounts * new_balance) + (num_stake_accounts * balance)
+        );
+        assert_eq!(
+            non_circulating_supply.accounts.len(),
+            num_non_circulating_accounts as usize + num_stake_accounts as usize
+        );
+
+        // Advance bank an epoch, which should unlock stakes
+        for _ in 0..slots_per_epoch {
+            bank = Arc::new(new_from_parent(bank));
+        }
+        assert_eq!(bank.epoch(), 1);
+        let non_circulating_supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(
+            non_circulating_supply.lamports,
+            num_non_circulating_accounts * new_balance
+        );
+        assert_eq!(
+            non_circulating_supply.accounts.len(),
+            num_non_circulating_accounts as usize
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_aggregate_lamports_by_authority_overflow() {
+        let authority = withdraw_authority()[0];
+        let other_authority = withdraw_authority()[1];
+        let near_max = u64::MAX - 1;
+
+        let totals =
+            aggregate_lamports_by_authority([(authority, near_max), (other_authority, near_max)])
+                .unwrap();
+        assert_eq!(totals[&authority], near_max);
+        assert_eq!(totals[&other_authority], near_max);
+
+        assert_eq!(
+            aggregate_lamports_by_authority([
+                (other_authority, 1),
+                (authority, near_max),
+                (authority, near_max),
+            ]),
+            Err(NonCirculatingSupplyError::AuthorityLamportsOverflow(
+                authority
+            )),
+        );
+    }
+
+    #[test]
+    fn test_authority_controlled_stake() {
+        let authority = solana_pubkey::new_rand();
+        let other_authority = solana_pubkey::new_rand();
+        let mut controlled = vec![solana_pubkey::new_rand(), solana_pubkey::new_rand()];
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            controlled[0],
+            new_stake_account(10, &authority, Lockup::default()),
+        );
+        accounts.insert(
+            controlled[1],
+            new_stake_account(
+                20,
+                &authority,
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(40, &other_authority, Lockup::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let (total, mut accounts) = authority_controlled_stake(&bank, &authority).unwrap();
+        assert_eq!(total, 30);
+        accounts.sort();
+        controlled.sort();
+        assert_eq!(accounts, controlled);
+
+        assert_eq!(
+            authority_controlled_stake(&bank, &solana_pubkey::new_rand()).unwrap(),
+            (0, vec![])
+        );
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_stake_account_slots() {
+        let withdrawer = solana_pubkey::new_rand();
+        let lockup = Lockup {
+            epoch: 10,
+            ..Lockup::default()
+        };
+        let genesis_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(genesis_stake, new_stake_account(10, &withdrawer, lockup));
+        let genesis_config = GenesisConfig {
+            accounts,
+            epoch_schedule: EpochSchedule::without_warmup(),
+            ..GenesisConfig::default()
+        };
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+
+        let bank1 = Arc::new(new_from_parent(bank0));
+        let slot1_stake = solana_pubkey::new_rand();
+        bank1.store_account(
+            &slot1_stake,
+            &new_stake_account(20, &withdrawer, lockup).into(),
+        );
+        let bank2 = new_from_parent(bank1);
+        let slot2_stake = solana_pubkey::new_rand();
+        bank2.store_account(
+            &slot2_stake,
+            &new_stake_account(40, &withdrawer, lockup).into(),
+        );
+
+        let calculate_for_slots = |slots: Range<Slot>| {
+            let config = NonCirculatingSupplyConfig {
+                stake_account_slots: Some(slots),
+                ..NonCirculatingSupplyConfig::default()
+            };
+            let supply = calculate_non_circulating_supply_with_config(&bank2, &config).unwrap();
+            let stake_accounts: HashSet<Pubkey> = supply
+                .accounts
+                .into_iter()
+                .filter(|pubkey| !non_circulating_accounts().contains(pubkey))
+                .collect();
+            (supply.lamports, stake_accounts)
+        };
+
+        assert_eq!(
+            calculate_for_slots(0..1),
+            (10, HashSet::from([genesis_stake]))
+        );
+        assert_eq!(
+            calculate_for_slots(1..3),
+            (60, HashSet::from([slot1_stake, slot2_stake]))
+        );
+        assert_eq!(
+            calculate_for_slots(2..3),
+            (40, HashSet::from([slot2_stake]))
+        );
+        assert_eq!(
+            calculate_non_circulating_supply(&bank2).unwrap().lamports,
+            70
+        );
+    }
+
+    #[test]
+    fn test_create_random_genesis_accounts_is_deterministic() {
+        let calculate_for_seed = |seed| {
+            let accounts = create_random_genesis_accounts(seed, 10, 10);
+            let genesis_config = GenesisConfig {
+                accounts: accounts.clone(),
+                ..GenesisConfig::default()
+            };
+            let bank = Bank::new_for_tests(&genesis_config);
+            let mut supply = calculate_non_circulating_supply(&bank).unwrap();
+            supply.accounts.sort();
+            (
+                bincode::serialize(&accounts).unwrap(),
+                bincode::serialize(&(supply.lamports, supply.accounts)).unwrap(),
+            )
+        };
+
+        let seed = 42;
+        assert_eq!(calculate_for_seed(seed), calculate_for_seed(seed));
+        assert_ne!(calculate_for_seed(seed).0, calculate_for_seed(seed + 1).0);
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_with_commitment() {
+        let slots_per_epoch = 32;
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(
+                10,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            epoch_schedule: EpochSchedule::custom(slots_per_epoch, slots_per_epoch, false),
+            ..GenesisConfig::default()
+        };
+        let bank_forks = BankForks::new_rw_arc(Bank::new_for_tests(&genesis_config));
+        let bank0 = bank_forks.read().unwrap().root_bank();
+        // The processed bank is in epoch 1, where the stake is no longer locked up
+        bank_forks.write().unwrap().insert(Bank::new_from_parent(
+            bank0,
+            &Pubkey::default(),
+            slots_per_epoch,
+        ));
+        let block_commitment_cache =
+            BlockCommitmentCache::new_for_tests_with_slots(slots_per_epoch, 0);
+
+        let bank_forks = bank_forks.read().unwrap();
+        let calculate_at = |commitment| {
+            calculate_non_circulating_supply_with_commitment(
+                &bank_forks,
+                &block_commitment_cache,
+                commitment,
+            )
+            .unwrap()
+            .lamports
+        };
+        assert_eq!(calculate_at(CommitmentLevel::Finalized), 10);
+        assert_eq!(calculate_at(CommitmentLevel::Confirmed), 10);
+        assert_eq!(calculate_at(CommitmentLevel::Processed), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_unlock_schedule() {
+        let mut accounts = BTreeMap::new();
+        let mut insert_stake = |lamports, withdrawer: &Pubkey, epoch| {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    withdrawer,
+                    Lockup {
+                        epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        };
+        let withdrawer = solana_pubkey::new_rand();
+        insert_stake(1, &withdrawer, 2);
+        insert_stake(2, &withdrawer, 2);
+        insert_stake(4, &withdrawer, 3);
+        insert_stake(8, &withdrawer, 5);
+        // Expired lockup
+        insert_stake(16, &withdrawer, 0);
+        // Controlled by a hardcoded withdraw authority, so never unlocks
+        insert_stake(32, &withdraw_authority()[0], 4);
+
+        let genesis_config = GenesisConfig {
+            accounts,
+            epoch_schedule: EpochSchedule::without_warmup(),
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert_eq!(
+            unlock_schedule(&bank).unwrap(),
+            BTreeMap::from([(2, 3), (3, 4), (5, 8)])
+        );
+    }
+
+    #[test]
+    fn test_distinct_unlock_epochs() {
+        let mut accounts = BTreeMap::new();
+        // Expired lockups don't count
+        for lockup_epoch in [0, 1, 1, 2, 5, 5, 5] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    10,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch: lockup_epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert_eq!(distinct_unlock_epochs(&bank).unwrap(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_locking_authority_of() {
+        let authority_locked = solana_pubkey::new_rand();
+        let lockup_locked = solana_pubkey::new_rand();
+        let authority = withdraw_authority()[2];
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            authority_locked,
+            new_stake_account(10, &authority, Lockup::default()),
+        );
+        accounts.insert(
+            lockup_locked,
+            new_stake_account(
+                10,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        assert_eq!(
+            locking_authority_of(&bank, &authority_locked).unwrap(),
+            Some(authority)
+        );
+        assert_eq!(locking_authority_of(&bank, &lockup_locked).unwrap(), None);
+        assert_eq!(
+            locking_authority_of(&bank, &solana_pubkey::new_rand()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_incinerator_as_burned() {
+        let (genesis_config, _mint_keypair) = create_genesis_config(500);
+        let bank = Bank::new_for_tests(&genesis_config);
+        let burned = 100;
+        bank.store_account(
+            &incinerator::id(),
+            &AccountSharedData::new(burned, 0, &Pubkey::default()),
+        );
+        let capitalization = bank.capitalization();
+
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(supply.burned_lamports, 0);
+        assert_eq!(supply.total_supply(capitalization), capitalization);
+
+        let config = NonCirculatingSupplyConfig {
+            incinerator_as_burned: true,
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let burned_supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(burned_supply.burned_lamports, burned);
+        assert_eq!(
+            burned_supply.total_supply(capitalization),
+            capitalization - burned
+        );
+        assert_eq!(burned_supply.lamports, supply.lamports);
+        assert!(!burned_supply.accounts.contains(&incinerator::id()));
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_calculate_non_circulating_supply_min_lamports() {
+        let hardcoded = non_circulating_accounts()[0];
+        let dust_stake = solana_pubkey::new_rand();
+        let stake = solana_pubkey::new_rand();
+        let withdrawer = solana_pubkey::new_rand();
+        let lockup = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let mut accounts = BTreeMap::new();
+        accounts.insert(hardcoded, Account::new(5, 0, &Pubkey::default()));
+        accounts.insert(dust_stake, new_stake_account(3, &withdrawer, lockup));
+        accounts.insert(stake, new_stake_account(100, &withdrawer, lockup));
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let mut config = NonCirculatingSupplyConfig {
+            min_lamports: Some(10),
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(supply.lamports, 105);
+        assert!(supply.accounts.contains(&hardcoded));
+        assert!(supply.accounts.contains(&stake));
+        assert!(!supply.accounts.contains(&dust_stake));
+
+        config.exclude_dust_hardcoded_from_list = true;
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(supply.lamports, 105);
+        assert!(!supply.accounts.contains(&hardcoded));
+        assert!(supply.accounts.contains(&stake));
+        assert!(!supply.accounts.contains(&dust_stake));
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_chunk() {
+        let withdrawer = solana_pubkey::new_rand();
+        let mut accounts = create_random_genesis_accounts(7, 5, 5);
+        for lamports in [7, 11] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(lamports, &withdrawer, Lockup::default()),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let first = calculate_non_circulating_supply_chunk(&bank, None, 4).unwrap();
+        assert!(first.cursor.is_some());
+        let second = calculate_non_circulating_supply_chunk(&bank, first.cursor, 4).unwrap();
+        assert!(second.cursor.is_none());
+
+        let mut merged = first.supply;
+        merged.merge(second.supply);
+        merged.accounts.sort();
+        let mut single_pass = calculate_non_circulating_supply(&bank).unwrap();
+        single_pass.accounts.sort();
+        assert_eq!(merged.lamports, single_pass.lamports);
+        assert_eq!(merged.accounts, single_pass.accounts);
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_recompute_for_authority_change() {
+        let old_authority = withdraw_authority()[0];
+        let new_authority = solana_pubkey::new_rand();
+        let released = solana_pubkey::new_rand();
+        let still_locked = solana_pubkey::new_rand();
+        let captured = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            released,
+            new_stake_account(10, &old_authority, Lockup::default()),
+        );
+        accounts.insert(
+            still_locked,
+            new_stake_account(
+                20,
+                &old_authority,
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        accounts.insert(
+            captured,
+            new_stake_account(40, &new_authority, Lockup::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let prev = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(prev.lamports, 30);
+        assert!(prev.accounts.contains(&released));
+        assert!(!prev.accounts.contains(&captured));
+
+        let rotated =
+            recompute_for_authority_change(&prev, &bank, &old_authority, &new_authority).unwrap();
+        assert_eq!(rotated.lamports, 60);
+        assert!(!rotated.accounts.contains(&released));
+        assert!(rotated.accounts.contains(&still_locked));
+        assert!(rotated.accounts.contains(&captured));
+        assert_eq!(rotated.accounts.len(), prev.accounts.len());
+
+        // An account folded into the dust isn't listed again, and leaves the dust when released
+        let config = NonCirculatingSupplyConfig {
+            consolidate_dust_below: Some(15),
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let prev = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        let prev_dust = prev.dust.unwrap();
+        assert!(!prev.accounts.contains(&released));
+        let rotated =
+            recompute_for_authority_change(&prev, &bank, &old_authority, &new_authority).unwrap();
+        assert_eq!(rotated.lamports, 60);
+        assert!(!rotated.accounts.contains(&released));
+        assert!(rotated.accounts.contains(&captured));
+        assert_eq!(
+            rotated.dust,
+            Some(ConsolidatedDust {
+                lamports: prev_dust.lamports - 10,
+                count: prev_dust.count - 1,
+            })
+        );
+
+        // A scaled total saturates rather than underflowing
+        let config = NonCirculatingSupplyConfig {
+            scale: LamportsScale {
+                numerator: 1,
+                denominator: 1_000,
+            },
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let prev = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(prev.lamports, 0);
+        assert!(
+            recompute_for_authority_change(&prev, &bank, &old_authority, &new_authority).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_locked_stake_ratio() {
+        let (genesis_config, _mint_keypair) = create_genesis_config(500);
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert_eq!(locked_stake_ratio(&bank).unwrap(), 0.0);
+
+        let withdrawer = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        for (lamports, epoch) in [(30, 1), (20, 2), (50, 0)] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    &withdrawer,
+                    Lockup {
+                        epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert!((locked_stake_ratio(&bank).unwrap() - 0.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_without_program_id_index() {
+        let accounts = create_random_genesis_accounts(209, 10, 10);
+        let hardcoded_accounts = non_circulating_accounts();
+        let expected_lamports: u64 = accounts
+            .iter()
+            .filter(|(pubkey, account)| {
+                account.owner == stake::program::id() || hardcoded_accounts.contains(pubkey)
+            })
+            .map(|(_pubkey, account)| account.lamports)
+            .sum();
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+
+        // Without the program-id index, stake accounts are loaded through get_program_accounts
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert!(!bank
+            .rc
+            .accounts
+            .accounts_db
+            .account_indexes
+            .contains(&AccountIndex::ProgramId));
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(supply.lamports, expected_lamports);
+        assert_eq!(supply.accounts.len(), hardcoded_accounts.len() + 10);
+
+        let mut account_indexes = AccountSecondaryIndexes::default();
+        account_indexes.indexes.insert(AccountIndex::ProgramId);
+        let indexed_bank = Bank::new_with_config_for_tests(
+            &genesis_config,
+            BankTestConfig {
+                accounts_db_config: AccountsDbConfig {
+                    account_indexes: Some(account_indexes),
+                    ..ACCOUNTS_DB_CONFIG_FOR_TESTING
+                },
+            },
+        );
+        let indexed_supply = calculate_non_circulating_supply(&indexed_bank).unwrap();
+        assert_eq!(indexed_supply.lamports, expected_lamports);
+        assert_eq!(indexed_supply.accounts.len(), supply.accounts.len());
+    }
+
+    #[derive(Debug)]
+    struct EscrowClassifier {
+        program_id: Pubkey,
+    }
+
+    impl ReasonClassifier for EscrowClassifier {
+        fn program_id(&self) -> Pubkey {
+            self.program_id
+        }
+
+        fn classify(
+            &self,
+            _pubkey: &Pubkey,
+            account: &AccountSharedData,
+            _clock: &Clock,
+        ) -> Option<NonCirculatingReason> {
+            (account.data().first() == Some(&1)).then_some(NonCirculatingReason::Custom("escrow"))
+        }
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_custom_classifier() {
+        let escrow_program = solana_pubkey::new_rand();
+        let locked_escrow = solana_pubkey::new_rand();
+        let unlocked_escrow = solana_pubkey::new_rand();
+        let locked_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            locked_escrow,
+            Account {
+                lamports: 10,
+                data: vec![1],
+                owner: escrow_program,
+                ..Account::default()
+            },
+        );
+        accounts.insert(
+            unlocked_escrow,
+            Account {
+                lamports: 20,
+                data: vec![0],
+                owner: escrow_program,
+                ..Account::default()
+            },
+        );
+        accounts.insert(
+            locked_stake,
+            new_stake_account(
+                40,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(supply.lamports, 40);
+        assert_eq!(supply.reasons[&locked_stake], NonCirculatingReason::Lockup);
+
+        let config = NonCirculatingSupplyConfig {
+            classifiers: vec![Arc::new(EscrowClassifier {
+                program_id: escrow_program,
+            })],
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(supply.lamports, 50);
+        assert_eq!(
+            supply.reasons[&locked_escrow],
+            NonCirculatingReason::Custom("escrow")
+        );
+        assert_eq!(supply.reasons[&locked_stake], NonCirculatingReason::Lockup);
+        assert!(!supply.reasons.contains_key(&unlocked_escrow));
+        for pubkey in non_circulating_accounts() {
+            assert_eq!(supply.reasons[&pubkey], NonCirculatingReason::Hardcoded);
+        }
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_epoch_schedule_override() {
+        let locked_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            locked_stake,
+            new_stake_account(
+                10,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 2,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            epoch_schedule: EpochSchedule::custom(32, 32, false),
+            ..GenesisConfig::default()
+        };
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+        let bank = Bank::new_from_parent(bank0, &Pubkey::default(), 64);
+        assert_eq!(bank.epoch(), 2);
+
+        // The lockup has expired by the bank's own schedule
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert!(!supply.reasons.contains_key(&locked_stake));
+
+        // With twice as many slots per epoch, slot 64 is still in epoch 1
+        let config = NonCirculatingSupplyConfig {
+            epoch_schedule: Some(EpochSchedule::custom(64, 64, false)),
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(supply.reasons[&locked_stake], NonCirculatingReason::Lockup);
+        assert_eq!(supply.lamports, 10);
+        assert_eq!(bank.epoch(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_authority_report() {
+        let withdraw_authority_list = withdraw_authority();
+        let authority = withdraw_authority_list[0];
+        let mut accounts = BTreeMap::new();
+        for lamports in [10, 20] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(lamports, &authority, Lockup::default()),
+            );
+        }
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(
+                40,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let report = authority_report(&bank).unwrap();
+        assert_eq!(report.len(), withdraw_authority_list.len());
+        for (entry, expected_authority) in report.iter().zip(withdraw_authority_list.iter()) {
+            assert_eq!(entry.0, *expected_authority);
+            if entry.0 == authority {
+                assert_eq!((entry.1, entry.2), (30, 2));
+            } else {
+                assert_eq!((entry.1, entry.2), (0, 0));
+            }
+        }
+
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        let authority_locked: u64 = supply
+            .reasons
+            .iter()
+            .filter(|(_pubkey, reason)| **reason == NonCirculatingReason::WithdrawAuthority)
+            .map(|(pubkey, _reason)| bank.get_balance(pubkey))
+            .sum();
+        let reported: u64 = report.iter().map(|(_authority, total, _count)| total).sum();
+        assert_eq!(reported, authority_locked);
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_withdrawer_census() {
+        let authority = withdraw_authority()[0];
+        let withdrawer = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        for (lamports, withdrawer) in [(10, authority), (20, authority), (50, withdrawer)] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(lamports, &withdrawer, Lockup::default()),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let census = withdrawer_census(&bank).unwrap();
+        assert_eq!(census, vec![(withdrawer, 1, 50), (authority, 2, 30)]);
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_consolidate_dust() {
+        let lockup = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let large_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            large_stake,
+            new_stake_account(1_000, &solana_pubkey::new_rand(), lockup),
+        );
+        for lamports in [1, 2, 3] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(lamports, &solana_pubkey::new_rand(), lockup),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let hardcoded_accounts = non_circulating_accounts();
+
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(supply.accounts.len(), hardcoded_accounts.len() + 4);
+        assert_eq!(supply.dust, None);
+
+        let config = NonCirculatingSupplyConfig {
+            consolidate_dust_below: Some(10),
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let consolidated = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(consolidated.lamports, supply.lamports);
+        assert!(consolidated.accounts.contains(&large_stake));
+        assert!(consolidated.accounts.iter().all(|pubkey| {
+            *pubkey == large_stake
+                || (hardcoded_accounts.contains(pubkey) && bank.get_balance(pubkey) >= 10)
+        }));
+        let dust = consolidated.dust.unwrap();
+        let listed_lamports: u64 = consolidated
+            .accounts
+            .iter()
+            .map(|pubkey| bank.get_balance(pubkey))
+            .sum();
+        assert_eq!(listed_lamports + dust.lamports, supply.lamports);
+        assert_eq!(
+            consolidated.accounts.len() + dust.count,
+            supply.accounts.len()
+        );
+        assert!(dust.count >= 3);
+    }
+
+    #[test]
+    fn test_balance_gini() {
+        let supply_with_balances = |balances: &[u64]| {
+            NonCirculatingSupply::new_for_tests(
+                balances.iter().sum(),
+                vec![],
+                None,
+                Some(
+                    balances
+                        .iter()
+                        .map(|balance| (solana_pubkey::new_rand(), *balance))
+                        .collect(),
+                ),
+            )
+        };
+        assert_eq!(supply_with_balances(&[]).balance_gini(), 0.0);
+        assert_eq!(supply_with_balances(&[100]).balance_gini(), 0.0);
+        assert_eq!(supply_with_balances(&[0, 0]).balance_gini(), 0.0);
+        assert!(supply_with_balances(&[5, 5, 5]).balance_gini().abs() < 1e-9);
+        assert!((supply_with_balances(&[0, 0, 0, 100]).balance_gini() - 0.75).abs() < 1e-9);
+        assert!((supply_with_balances(&[10, 20, 30]).balance_gini() - 2.0 / 9.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_accounts_diff() {
+        let new_supply = |balances: &[(Pubkey, u64)]| {
+            let reasons = balances
+                .iter()
+                .map(|(pubkey, _lamports)| (*pubkey, NonCirculatingReason::Lockup))
+                .collect();
+            NonCirculatingSupply::new_for_tests(
+                balances.iter().map(|(_pubkey, lamports)| lamports).sum(),
+                balances.iter().map(|(pubkey, _lamports)| *pubkey).collect(),
+                Some(reasons),
+                Some(balances.iter().copied().collect()),
+            )
+        };
+        let mut pubkeys: Vec<Pubkey> = (0..4).map(|_| solana_pubkey::new_rand()).collect();
+        pubkeys.sort_unstable();
+
+        // Only balances change
+        let old = new_supply(&[(pubkeys[0], 10), (pubkeys[1], 20)]);
+        let new = new_supply(&[(pubkeys[0], 15), (pubkeys[1], 5)]);
+        assert_eq!(accounts_diff(&old, &new), (vec![], vec![]));
+
+        let new = new_supply(&[(pubkeys[3], 1), (pubkeys[1], 20), (pubkeys[2], 1)]);
+        assert_eq!(
+            accounts_diff(&old, &new),
+            (vec![pubkeys[2], pubkeys[3]], vec![pubkeys[0]])
+        );
+    }
+
+    #[test]
+    fn test_supply_delta() {
+        let new_supply = |balances: &[(Pubkey, u64)]| {
+            let reasons = balances
+                .iter()
+                .map(|(pubkey, _lamports)| (*pubkey, NonCirculatingReason::Lockup))
+                .collect();
+            NonCirculatingSupply::new_for_tests(
+                balances.iter().map(|(_pubkey, lamports)| lamports).sum(),
+                balances.iter().map(|(pubkey, _lamports)| *pubkey).collect(),
+                Some(reasons),
+                Some(balances.iter().copied().collect()),
+            )
+        };
+        let mut pubkeys: Vec<Pubkey> = (0..5).map(|_| solana_pubkey::new_rand()).collect();
+        pubkeys.sort_unstable();
+
+        let old = new_supply(&[
+            (pubkeys[0], 1_000),
+            (pubkeys[1], 1_000),
+            (pubkeys[2], 1_000),
+            (pubkeys[3], 5),
+        ]);
+        let new = new_supply(&[
+            // Small changes
+            (pubkeys[0], 1_010),
+            (pubkeys[1], 995),
+            // Large change
+            (pubkeys[2], 500),
+            // Small added account
+            (pubkeys[4], 1),
+        ]);
+        let delta = supply_delta(&old, &new, 100);
+        assert_eq!(delta.lamports_delta, -499);
+        assert_eq!(delta.changed, vec![(pubkeys[2], -500)]);
+        // Added and removed regardless of their balances
+        assert_eq!(delta.added, vec![(pubkeys[4], 1)]);
+        assert_eq!(delta.removed, vec![(pubkeys[3], 5)]);
+
+        let delta = supply_delta(&old, &new, 0);
+        assert_eq!(
+            delta.changed,
+            vec![(pubkeys[0], 10), (pubkeys[1], -5), (pubkeys[2], -500)]
+        );
+    }
+
+    #[test]
+    fn test_revalidate() {
+        let expiring = solana_pubkey::new_rand();
+        let locked = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        for (pubkey, epoch) in [(expiring, 1), (locked, 2)] {
+            accounts.insert(
+                pubkey,
+                new_stake_account(
+                    10,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+        let cached = calculate_non_circulating_supply(&bank0).unwrap();
+        assert!(revalidate(&cached, &bank0).unwrap().is_empty());
+
+        let slot = bank0.epoch_schedule().get_first_slot_in_epoch(1);
+        let bank1 = Bank::new_from_parent(bank0, &Pubkey::default(), slot);
+        assert_eq!(revalidate(&cached, &bank1).unwrap(), vec![expiring]);
+    }
+
+    #[test]
+    fn test_calculate_with_overrides() {
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            stake,
+            new_stake_account(500, &solana_pubkey::new_rand(), locked),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let committed = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(committed.balances[&stake], 500);
+
+        let mut overridden_stake = bank.get_account(&stake).unwrap();
+        overridden_stake.set_lamports(700);
+        let new_stake = solana_pubkey::new_rand();
+        let overrides = HashMap::from([
+            (stake, overridden_stake),
+            (
+                new_stake,
+                AccountSharedData::from(new_stake_account(50, &solana_pubkey::new_rand(), locked)),
+            ),
+        ]);
+        let supply = calculate_with_overrides(&bank, &overrides).unwrap();
+        assert_eq!(supply.balances[&stake], 700);
+        assert_eq!(supply.balances[&new_stake], 50);
+        assert_eq!(supply.lamports, committed.lamports + 200 + 50);
+        // Nothing was committed
+        assert_eq!(bank.get_balance(&stake), 500);
+        assert!(bank.get_account(&new_stake).is_none());
+
+        // Overriding with zero lamports deletes the account
+        let overrides = HashMap::from([(stake, AccountSharedData::default())]);
+        let supply = calculate_with_overrides(&bank, &overrides).unwrap();
+        assert!(!supply.reasons.contains_key(&stake));
+        assert_eq!(supply.lamports, committed.lamports - 500);
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_stale_authorities() {
+        let withdraw_authority_list = withdraw_authority();
+        let (used_authority, locked_authority) =
+            (withdraw_authority_list[0], withdraw_authority_list[1]);
+        let mut accounts = BTreeMap::new();
+        for (withdrawer, epoch) in [
+            (used_authority, 0),
+            // In use even though the account is counted for its lockup
+            (locked_authority, 1),
+            (solana_pubkey::new_rand(), 0),
+        ] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    10,
+                    &withdrawer,
+                    Lockup {
+                        epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let expected: Vec<Pubkey> = withdraw_authority_list[2..].to_vec();
+
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(supply.stale_authorities, expected);
+
+        // Merged chunks agree with the full scan
+        let first = calculate_non_circulating_supply_chunk(&bank, None, 1).unwrap();
+        let mut merged = first.supply;
+        let mut cursor = first.cursor;
+        while let Some(chunk_cursor) = cursor {
+            let chunk =
+                calculate_non_circulating_supply_chunk(&bank, Some(chunk_cursor), 1).unwrap();
+            merged.merge(chunk.supply);
+            cursor = chunk.cursor;
+        }
+        assert_eq!(merged.stale_authorities, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_recompute_on_epoch_change() {
+        let authority = withdraw_authority()[0];
+        let mut accounts = BTreeMap::new();
+        for (lamports, withdrawer, epoch) in [
+            (10, solana_pubkey::new_rand(), 1),
+            (20, solana_pubkey::new_rand(), 2),
+            (40, authority, 1),
+            (80, solana_pubkey::new_rand(), 0),
+        ] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    &withdrawer,
+                    Lockup {
+                        epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+        let mut supply = calculate_non_circulating_supply(&bank0).unwrap();
+        let slot = bank0.epoch_schedule().get_first_slot_in_epoch(1);
+        let bank1 = Bank::new_from_parent(bank0, &Pubkey::default(), slot);
+
+        supply
+            .recompute_on_epoch_change(&bank1, &NonCirculatingSupplyConfig::default())
+            .unwrap();
+        let expected = calculate_non_circulating_supply(&bank1).unwrap();
+        assert_eq!(supply.epoch, 1);
+        assert_eq!(supply.lamports, expected.lamports);
+        assert_eq!(supply.reasons, expected.reasons);
+        assert_eq!(supply.balances, expected.balances);
+        assert_eq!(supply.static_lamports, expected.static_lamports);
+        let mut accounts = supply.accounts.clone();
+        accounts.sort_unstable();
+        let mut expected_accounts = expected.accounts.clone();
+        expected_accounts.sort_unstable();
+        assert_eq!(accounts, expected_accounts);
+    }
+
+    #[test]
+    fn test_recompute_on_epoch_change_with_config() {
+        let authority = solana_pubkey::new_rand();
+        let expiring = solana_pubkey::new_rand();
+        let expiring_to_authority = solana_pubkey::new_rand();
+        let locked = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        for (pubkey, lamports, withdrawer, epoch) in [
+            (expiring, 10, solana_pubkey::new_rand(), 1),
+            (expiring_to_authority, 20, authority, 1),
+            (locked, 40, solana_pubkey::new_rand(), 2),
+        ] {
+            accounts.insert(
+                pubkey,
+                new_stake_account(
+                    lamports,
+                    &withdrawer,
+                    Lockup {
+                        epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let config = NonCirculatingSupplyConfig {
+            additional_withdraw_authorities: vec![authority],
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+        let mut supply = calculate_non_circulating_supply_with_config(&bank0, &config).unwrap();
+        assert_eq!(supply.lockups.len(), 3);
+        let slot = bank0.epoch_schedule().get_first_slot_in_epoch(1);
+        let bank1 = Bank::new_from_parent(bank0, &Pubkey::default(), slot);
+
+        supply.recompute_on_epoch_change(&bank1, &config).unwrap();
+        let expected = calculate_non_circulating_supply_with_config(&bank1, &config).unwrap();
+        assert_eq!(supply.lamports, expected.lamports);
+        assert_eq!(supply.reasons, expected.reasons);
+        assert_eq!(
+            supply.reasons[&expiring_to_authority],
+            NonCirculatingReason::WithdrawAuthority
+        );
+        assert_eq!(supply.dynamic_lamports, expected.dynamic_lamports);
+        assert_eq!(supply.dynamic_lamports, 20);
+        assert_eq!(supply.lockups, expected.lockups);
+
+        // Accounts whose lockup is still in force aren't refetched, so closing one goes unseen
+        // until its lockup expires
+        let mut supply = calculate_non_circulating_supply_with_config(&bank1, &config).unwrap();
+        bank1.store_account(&locked, &AccountSharedData::default());
+        supply.recompute_on_epoch_change(&bank1, &config).unwrap();
+        assert_eq!(supply.balances[&locked], 40);
+    }
+
+    #[test]
+    fn test_recompute_on_epoch_change_count_cooldown_stake() {
+        let cooling_down_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            cooling_down_stake,
+            new_delegated_stake_account(
+                50,
+                &solana_pubkey::new_rand(),
+                Lockup::default(),
+                Delegation {
+                    voter_pubkey: solana_pubkey::new_rand(),
+                    stake: 40,
+                    // Bootstrap stake, fully effective until its deactivation
+                    activation_epoch: u64::MAX,
+                    deactivation_epoch: 0,
+                    ..Delegation::default()
+                },
+            ),
+        );
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(
+                30,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let config = NonCirculatingSupplyConfig {
+            count_cooldown_stake: true,
+            ..NonCirculatingSupplyConfig::default()
+        };
+        assert!(!config.is_incremental());
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+        let mut supply = calculate_non_circulating_supply_with_config(&bank0, &config).unwrap();
+        assert_eq!(
+            supply.reasons[&cooling_down_stake],
+            NonCirculatingReason::Cooldown
+        );
+        let slot = bank0.epoch_schedule().get_first_slot_in_epoch(1);
+        let bank1 = Bank::new_from_parent(bank0, &Pubkey::default(), slot);
+
+        supply.recompute_on_epoch_change(&bank1, &config).unwrap();
+        let expected = calculate_non_circulating_supply_with_config(&bank1, &config).unwrap();
+        assert_eq!(supply.epoch, 1);
+        assert_eq!(supply.lamports, expected.lamports);
+        assert_eq!(supply.reasons, expected.reasons);
+        assert_eq!(supply.balances, expected.balances);
+        assert_eq!(supply.cooldown_lamports, expected.cooldown_lamports);
+    }
+
+    #[test]
+    fn test_supply_alarm() {
+        // 1% tolerance
+        assert_eq!(supply_alarm(10_000, 10_000, 100), None);
+        assert_eq!(supply_alarm(10_000, 10_100, 100), None);
+        assert_eq!(supply_alarm(10_000, 9_900, 100), None);
+        assert_eq!(
+            supply_alarm(10_000, 10_101, 100),
+            Some(SupplyAlarm {
+                direction: SupplyChangeDirection::Increase,
+                delta_lamports: 101,
+                change_bps: 101,
+            })
+        );
+        assert_eq!(
+            supply_alarm(10_000, 8_000, 100),
+            Some(SupplyAlarm {
+                direction: SupplyChangeDirection::Decrease,
+                delta_lamports: 2_000,
+                change_bps: 2_000,
+            })
+        );
+        assert_eq!(supply_alarm(0, 0, 0), None);
+        assert_eq!(
+            supply_alarm(0, 1, 100),
+            Some(SupplyAlarm {
+                direction: SupplyChangeDirection::Increase,
+                delta_lamports: 1,
+                change_bps: u64::MAX,
+            })
+        );
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_token_lock_classifier() {
+        let token_lock_program = solana_pubkey::new_rand();
+        let locked_offset = 8;
+        let wrapper = solana_pubkey::new_rand();
+        let unlocked_wrapper = solana_pubkey::new_rand();
+        let wrapper_data = |locked_lamports: u64| {
+            let mut data = vec![0xff; locked_offset];
+            data.extend_from_slice(&locked_lamports.to_le_bytes());
+            data
+        };
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            wrapper,
+            Account {
+                lamports: 100,
+                data: wrapper_data(60),
+                owner: token_lock_program,
+                ..Account::default()
+            },
+        );
+        accounts.insert(
+            unlocked_wrapper,
+            Account {
+                lamports: 100,
+                data: wrapper_data(0),
+                owner: token_lock_program,
+                ..Account::default()
+            },
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let baseline = calculate_non_circulating_supply(&bank).unwrap();
+
+        let config = NonCirculatingSupplyConfig {
+            classifiers: vec![Arc::new(TokenLockClassifier::new(
+                token_lock_program,
+                locked_offset,
+            ))],
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(supply.lamports, baseline.lamports + 60);
+        assert_eq!(
+            supply.reasons[&wrapper],
+            NonCirculatingReason::Custom(TokenLockClassifier::REASON)
+        );
+        assert_eq!(supply.balances[&wrapper], 60);
+        assert!(!supply.reasons.contains_key(&unlocked_wrapper));
+    }
+
+    #[test]
+    fn test_linear_vesting_classifier() {
+        let vesting_program = solana_pubkey::new_rand();
+        let escrow = solana_pubkey::new_rand();
+        let schedule_offset = 32;
+        let genesis_config = GenesisConfig::default();
+        let now = Bank::new_for_tests(&genesis_config).clock().unix_timestamp;
+        let (start_timestamp, end_timestamp) = (now - 100, now + 300);
+        let mut data = vec![0; schedule_offset + LinearVestingClassifier::SCHEDULE_LEN];
+        data[schedule_offset..][..8].copy_from_slice(&start_timestamp.to_le_bytes());
+        data[schedule_offset..][8..16].copy_from_slice(&end_timestamp.to_le_bytes());
+        data[schedule_offset..][16..24].copy_from_slice(&800u64.to_le_bytes());
+        let escrow_account = Account {
+            lamports: 1_000,
+            data,
+            owner: vesting_program,
+            ..Account::default()
+        };
+        let classifier = LinearVestingClassifier::new(vesting_program, schedule_offset);
+
+        // Locked portion along the vesting curve
+        let shared_account = AccountSharedData::from(escrow_account.clone());
+        let locked_at = |unix_timestamp| {
+            let clock = Clock {
+                unix_timestamp,
+                ..Clock::default()
+            };
+            classifier
+                .classify(&escrow, &shared_account, &clock)
+                .map(|_reason| {
+                    classifier.non_circulating_lamports(&escrow, &shared_account, &clock)
+                })
+        };
+        assert_eq!(locked_at(start_timestamp - 1), Some(800));
+        assert_eq!(locked_at(start_timestamp), Some(800));
+        assert_eq!(locked_at(start_timestamp + 100), Some(600));
+        assert_eq!(locked_at(start_timestamp + 200), Some(400));
+        assert_eq!(locked_at(start_timestamp + 300), Some(200));
+        assert_eq!(locked_at(end_timestamp), None);
+
+        let mut accounts = BTreeMap::new();
+        accounts.insert(escrow, escrow_account);
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..genesis_config
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert_eq!(bank.clock().unix_timestamp, now);
+        let baseline = calculate_non_circulating_supply(&bank).unwrap();
+        let config = NonCirculatingSupplyConfig {
+            classifiers: vec![Arc::new(classifier)],
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(supply.lamports, baseline.lamports + 600);
+        assert_eq!(supply.balances[&escrow], 600);
+        assert_eq!(
+            supply.reasons[&escrow],
+            NonCirculatingReason::Custom(LinearVestingClassifier::REASON)
+        );
+    }
+
+    #[test]
+    fn test_frozen_account_classifier() {
+        let token_program = solana_pubkey::new_rand();
+        let frozen = solana_pubkey::new_rand();
+        let unfrozen = solana_pubkey::new_rand();
+        let token_account_data = |state: u8| {
+            let mut data = vec![0; 165];
+            data[FrozenAccountClassifier::SPL_TOKEN_STATE_OFFSET] = state;
+            data
+        };
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            frozen,
+            Account {
+                lamports: 100,
+                data: token_account_data(FrozenAccountClassifier::SPL_TOKEN_FROZEN_STATE),
+                owner: token_program,
+                ..Account::default()
+            },
+        );
+        accounts.insert(
+            unfrozen,
+            Account {
+                lamports: 200,
+                // `AccountState::Initialized`
+                data: token_account_data(1),
+                owner: token_program,
+                ..Account::default()
+            },
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let baseline = calculate_non_circulating_supply(&bank).unwrap();
+        assert!(!baseline.reasons.contains_key(&frozen));
+
+        let config = NonCirculatingSupplyConfig {
+            classifiers: vec![Arc::new(FrozenAccountClassifier::spl_token(token_program))],
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(supply.lamports, baseline.lamports + 100);
+        assert_eq!(
+            supply.reasons[&frozen],
+            NonCirculatingReason::Custom(FrozenAccountClassifier::REASON)
+        );
+        assert_eq!(supply.balances[&frozen], 100);
+        assert!(!supply.reasons.contains_key(&unfrozen));
+    }
+
+    #[test]
+    fn test_circulating_supply_net_of_foundation_reserve() {
+        let reserve = solana_pubkey::new_rand();
+        let locked_reserve = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(reserve, Account::new(1_000, 0, &Pubkey::default()));
+        accounts.insert(
+            locked_reserve,
+            new_stake_account(
+                500,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let capitalization = bank.capitalization();
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(supply.reserve_lamports, 0);
+
+        let config = NonCirculatingSupplyConfig {
+            foundation_reserve: vec![reserve, locked_reserve],
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let reserve_supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        // The locked reserve account is already non-circulating
+        assert_eq!(reserve_supply.reserve_lamports, 1_000);
+        assert_eq!(reserve_supply.lamports, supply.lamports);
+        assert_eq!(
+            reserve_supply.circulating_supply(capitalization),
+            supply.circulating_supply(capitalization) - 1_000
+        );
+    }
+
+    #[test]
+    fn test_has_supply_changed() {
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(
+                500,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Arc::new(Bank::new_for_tests(&genesis_config));
+        let checksum = calculate_non_circulating_supply(&bank).unwrap().checksum();
+        assert!(!has_supply_changed(&bank, checksum).unwrap());
+
+        // The lockup expires in epoch 1
+        let slot = bank.epoch_schedule().get_first_slot_in_epoch(1);
+        let bank = Bank::new_from_parent(bank, &Pubkey::default(), slot);
+        assert!(has_supply_changed(&bank, checksum).unwrap());
+    }
+
+    #[test]
+    fn test_supply_merkle_leaf() {
+        let pubkey = solana_pubkey::new_rand();
+        let supply = NonCirculatingSupply::new_for_tests(
+            100,
+            vec![pubkey],
+            Some(HashMap::from([(pubkey, NonCirculatingReason::Lockup)])),
+            Some(HashMap::from([(pubkey, 100)])),
+        );
+        let leaf = supply_merkle_leaf(&supply, 10);
+        assert_eq!(leaf, supply_merkle_leaf(&supply.clone(), 10));
+
+        assert_ne!(leaf, supply_merkle_leaf(&supply, 11));
+        let mut changed = supply.clone();
+        changed.lamports += 1;
+        assert_ne!(leaf, supply_merkle_leaf(&changed, 10));
+        let mut changed = supply.clone();
+        changed.epoch += 1;
+        assert_ne!(leaf, supply_merkle_leaf(&changed, 10));
+        let mut changed = supply.clone();
+        changed.balances.insert(pubkey, 99);
+        assert_ne!(leaf, supply_merkle_leaf(&changed, 10));
+    }
+
+    #[test]
+    fn test_verify_double_scan() {
+        let mut accounts = BTreeMap::new();
+        for lamports in 1..=5 {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch: 1,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        // The first scan goes through the program id index, the second one doesn't
+        let mut account_indexes = AccountSecondaryIndexes::default();
+        account_indexes.indexes.insert(AccountIndex::ProgramId);
+        let bank = Bank::new_with_config_for_tests(
+            &genesis_config,
+            BankTestConfig {
+                accounts_db_config: AccountsDbConfig {
+                    account_indexes: Some(account_indexes),
+                    ..ACCOUNTS_DB_CONFIG_FOR_TESTING
+                },
+            },
+        );
+        let expected = calculate_non_circulating_supply(&bank).unwrap();
+
+        // Expensive audit mode, scanning every classified program twice
+        let config = NonCirculatingSupplyConfig {
+            verify_double_scan: true,
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(supply.lamports, expected.lamports);
+        assert_eq!(supply.checksum(), expected.checksum());
+        let verified = calculate_non_circulating_supply_verified(
+            &bank,
+            &NonCirculatingSupplyConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(verified.checksum(), expected.checksum());
+
+        let stake_accounts = load_stake_accounts(&bank).unwrap();
+        assert_eq!(
+            verify_double_scan(
+                &bank,
+                &bank.ancestors,
+                bank.bank_id(),
+                &stake::program::id(),
+                &stake_accounts
+            )
+            .unwrap(),
+            Ok(())
+        );
+        assert_eq!(
+            verify_double_scan(
+                &bank,
+                &bank.ancestors,
+                bank.bank_id(),
+                &stake::program::id(),
+                &stake_accounts[1..]
+            )
+            .unwrap(),
+            Err(DivergenceError {
+                program_id: stake::program::id(),
+                pubkey: stake_accounts[0].0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_supply_monotonic_circulating_across_epoch() {
+        let mut accounts = BTreeMap::new();
+        for (lamports, epoch) in [(10, 1), (20, 2), (40, 0)] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            epoch_schedule: EpochSchedule::custom(32, 32, false),
+            ..GenesisConfig::default()
+        };
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+        let bank1 = Arc::new(Bank::new_from_parent(bank0.clone(), &Pubkey::default(), 32));
+        assert_eq!(bank1.epoch(), 1);
+        let bank2 = Bank::new_from_parent(bank1.clone(), &Pubkey::default(), 64);
+        assert_eq!(bank2.epoch(), 2);
+
+        assert_supply_monotonic_circulating(&bank0, &bank1);
+        assert_supply_monotonic_circulating(&bank1, &bank2);
+        assert_eq!(
+            calculate_non_circulating_supply(&bank0).unwrap().lamports
+                - calculate_non_circulating_supply(&bank2).unwrap().lamports,
+            30
+        );
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_exclude_rent_exempt_reserve() {
+        let locked_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            locked_stake,
+            new_stake_account(
+                1_000_000_000,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+
+        let rent = Rent {
+            lamports_per_byte_year: 1_000,
+            exemption_threshold: 2.0,
+            burn_percent: 0,
+        };
+        let reserve = rent.minimum_balance(StakeStateV2::size_of());
+        assert_ne!(
+            reserve,
+            bank.rent_collector()
+                .rent
+                .minimum_balance(StakeStateV2::size_of())
+        );
+        let config = NonCirculatingSupplyConfig {
+            exclude_rent_exempt_reserve: true,
+            rent: Some(rent),
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let excluding_reserve =
+            calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(supply.lamports - excluding_reserve.lamports, reserve);
+        assert_eq!(
+            excluding_reserve.balances[&locked_stake],
+            1_000_000_000 - reserve
+        );
+
+        // Without an override, the bank's rent sizes the reserve
+        let config = NonCirculatingSupplyConfig {
+            exclude_rent_exempt_reserve: true,
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let excluding_reserve =
+            calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(
+            supply.lamports - excluding_reserve.lamports,
+            bank.rent_collector()
+                .rent
+                .minimum_balance(StakeStateV2::size_of())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_aggregates_serialize_deterministically() {
+        let withdraw_authority_list = withdraw_authority();
+        let mut accounts = BTreeMap::new();
+        for (i, authority) in withdraw_authority_list.iter().enumerate() {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(10 + i as u64, authority, Lockup::default()),
+            );
+        }
+        for lamports in [1, 2, 3] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch: 1,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        // Each calculation builds fresh, differently seeded hash maps
+        let by_reason = || {
+            bincode::serialize(
+                &calculate_non_circulating_supply(&bank)
+                    .unwrap()
+                    .lamports_by_reason(),
+            )
+            .unwrap()
+        };
+        assert_eq!(by_reason(), by_reason());
+        let by_authority =
+            || bincode::serialize(&withdraw_authority_lamports(&bank).unwrap()).unwrap();
+        assert_eq!(by_authority(), by_authority());
+
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        let reasons: Vec<_> = supply.lamports_by_reason().into_keys().collect();
+        assert_eq!(
+            reasons,
+            vec![
+                NonCirculatingReason::Hardcoded,
+                NonCirculatingReason::Lockup,
+                NonCirculatingReason::WithdrawAuthority,
+            ]
+        );
+        assert_eq!(
+            supply.lamports_by_reason()[&NonCirculatingReason::Lockup],
+            6
+        );
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_parallel_decode_failure() {
+        let corrupt_stake = solana_pubkey::new_rand();
+        let locked_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            corrupt_stake,
+            Account {
+                lamports: 10,
+                data: vec![0xff; 16],
+                owner: stake::program::id(),
+                ..Account::default()
+            },
+        );
+        accounts.insert(
+            locked_stake,
+            new_stake_account(
+                20,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let serial = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(serial.undecodable_accounts, vec![corrupt_stake]);
+
+        let config = NonCirculatingSupplyConfig {
+            parallel: true,
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let parallel = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(parallel.undecodable_accounts, vec![corrupt_stake]);
+        assert_eq!(parallel.lamports, serial.lamports);
+        assert_eq!(parallel.reasons, serial.reasons);
+        assert!(!parallel.reasons.contains_key(&corrupt_stake));
+
+        let config = NonCirculatingSupplyConfig {
+            parallel: true,
+            decode_failure_mode: DecodeFailureMode::Strict,
+            ..NonCirculatingSupplyConfig::default()
+        };
+        assert_eq!(
+            calculate_non_circulating_supply_with_config(&bank, &config).err(),
+            Some(ScanError::Aborted(format!(
+                "failed to deserialize stake account {corrupt_stake}"
+            )))
+        );
+    }
+
+    #[test]
+    fn test_account_contribution_delta() {
+        let withdrawer = solana_pubkey::new_rand();
+        let locked = |lamports| -> AccountSharedData {
+            new_stake_account(
+                lamports,
+                &withdrawer,
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            )
+            .into()
+        };
+        let unlocked = |lamports| -> AccountSharedData {
+            new_stake_account(lamports, &withdrawer, Lockup::default()).into()
+        };
+        let clock = Clock::default();
+
+        // Newly locked
+        assert_eq!(
+            account_contribution_delta(&unlocked(10), &locked(10), &clock),
+            10
+        );
+        // Unlocked
+        assert_eq!(
+            account_contribution_delta(&locked(10), &unlocked(10), &clock),
+            -10
+        );
+        // Balance changed while locked
+        assert_eq!(
+            account_contribution_delta(&locked(10), &locked(25), &clock),
+            15
+        );
+        assert_eq!(
+            account_contribution_delta(&locked(25), &locked(10), &clock),
+            -15
+        );
+        // Still circulating, whatever the balance
+        assert_eq!(
+            account_contribution_delta(&unlocked(10), &unlocked(25), &clock),
+            0
+        );
+        // Created locked, and closed while locked
+        let closed = AccountSharedData::default();
+        assert_eq!(account_contribution_delta(&closed, &locked(10), &clock), 10);
+        assert_eq!(
+            account_contribution_delta(&locked(10), &closed, &clock),
+            -10
+        );
+        // Lockup expired by the clock while the account is unchanged
+        let expired = Clock {
+            epoch: 1,
+            ..Clock::default()
+        };
+        assert_eq!(
+            account_contribution_delta(&locked(10), &locked(10), &expired),
+            0
+        );
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_hard_custodians() {
+        let hard_custodian = solana_pubkey::new_rand();
+        let soft_locked = solana_pubkey::new_rand();
+        let hard_locked = solana_pubkey::new_rand();
+        let time_locked = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        for (pubkey, lamports, custodian) in [
+            (soft_locked, 10, solana_pubkey::new_rand()),
+            (hard_locked, 20, hard_custodian),
+            (time_locked, 40, Pubkey::default()),
+        ] {
+            accounts.insert(
+                pubkey,
+                new_stake_account(
+                    lamports,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch: 1,
+                        custodian,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        for pubkey in [soft_locked, hard_locked, time_locked] {
+            assert_eq!(supply.reasons[&pubkey], NonCirculatingReason::Lockup);
+        }
+
+        let config = NonCirculatingSupplyConfig {
+            hard_custodians: Some(vec![hard_custodian]),
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let custodian_aware = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert!(!custodian_aware.reasons.contains_key(&soft_locked));
+        assert_eq!(
+            custodian_aware.reasons[&hard_locked],
+            NonCirculatingReason::Lockup
+        );
+        assert_eq!(
+            custodian_aware.reasons[&time_locked],
+            NonCirculatingReason::Lockup
+        );
+        assert_eq!(custodian_aware.lamports, supply.lamports - 10);
+    }
+
+    #[test]
+    fn test_next_epoch_projection_unix_timestamp_lockup() {
+        let genesis_config = GenesisConfig {
+            epoch_schedule: EpochSchedule::custom(32, 32, false),
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        // Locked until the extrapolated timestamp of the next epoch's first slot
+        let stake = solana_pubkey::new_rand();
+        let lockup = Lockup {
+            unix_timestamp: clock_at_epoch(&bank, 1).unix_timestamp,
+            ..Lockup::default()
+        };
+        assert!(lockup.unix_timestamp > bank.clock().unix_timestamp);
+        bank.store_account(
+            &stake,
+            &new_stake_account(10, &solana_pubkey::new_rand(), lockup).into(),
+        );
+
+        let current = calculate_non_circulating_supply(&bank).unwrap();
+        assert!(current.reasons.contains_key(&stake));
+        let projection = next_epoch_projection(&bank).unwrap();
+        assert!(!projection.reasons.contains_key(&stake));
+    }
+
+    #[test]
+    fn test_next_epoch_projection() {
+        let unlocking_stake = solana_pubkey::new_rand();
+        let locked_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        for (pubkey, lamports, epoch) in [(unlocking_stake, 10, 1), (locked_stake, 20, 2)] {
+            accounts.insert(
+                pubkey,
+                new_stake_account(
+                    lamports,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            epoch_schedule: EpochSchedule::custom(32, 32, false),
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert_eq!(bank.epoch(), 0);
+
+        let current = calculate_non_circulating_supply(&bank).unwrap();
+        assert!(current.reasons.contains_key(&unlocking_stake));
+        assert!(current.reasons.contains_key(&locked_stake));
+
+        let projection = next_epoch_projection(&bank).unwrap();
+        assert!(!projection.reasons.contains_key(&unlocking_stake));
+        assert!(projection.reasons.contains_key(&locked_stake));
+        assert_eq!(projection.lamports, current.lamports - 10);
+
+        // The projection matches the supply once the next epoch is reached
+        let next_slot = bank.epoch_schedule().get_first_slot_in_epoch(1);
+        let next_bank = Bank::new_from_parent(Arc::new(bank), &Pubkey::default(), next_slot);
+        assert_eq!(
+            calculate_non_circulating_supply(&next_bank)
+                .unwrap()
+                .lamports,
+            projection.lamports
+        );
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_across_rewards() {
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let new_locked_stake_account = |lamports: u64| {
+            new_delegated_stake_account(
+                lamports,
+                &solana_pubkey::new_rand(),
+                locked,
+                Delegation {
+                    voter_pubkey: solana_pubkey::new_rand(),
+                    stake: lamports,
+                    activation_epoch: u64::MAX,
+                    ..Delegation::default()
+                },
+            )
+        };
+        let rewarded = solana_pubkey::new_rand();
+        let deposited = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(rewarded, new_locked_stake_account(1_000));
+        accounts.insert(deposited, new_locked_stake_account(1_000));
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+        let before = calculate_non_circulating_supply(&bank0).unwrap();
+
+        let bank1 = new_from_parent(bank0.clone());
+        // Rewards are paid into the delegated stake
+        let mut account = bank1.get_account(&rewarded).unwrap();
+        let Some(StakeStateV2::Stake(meta, mut stake, stake_flags)) = stake_state::from(&account)
+        else {
+            panic!("not a delegated stake account");
+        };
+        stake.delegation.stake += 100;
+        account.set_lamports(account.lamports() + 100);
+        account
+            .serialize_data(&StakeStateV2::Stake(meta, stake, stake_flags))
+            .unwrap();
+        bank1.store_account(&rewarded, &account);
+        // A deposit only grows the balance
+        let mut account = bank1.get_account(&deposited).unwrap();
+        account.set_lamports(account.lamports() + 50);
+        bank1.store_account(&deposited, &account);
+
+        let supply = calculate_non_circulating_supply_across_rewards(&bank0, &bank1).unwrap();
+        assert_eq!(supply.lamports, before.lamports + 150);
+        assert_eq!(supply.locked_rewards_lamports, 100);
+        assert_eq!(
+            calculate_non_circulating_supply(&bank1)
+                .unwrap()
+                .locked_rewards_lamports,
+            0
+        );
+    }
+
+    #[test]
+    fn test_non_circulating_supply_epoch_start() {
+        let genesis_config = GenesisConfig::default();
+        let epoch_start_timestamp = Bank::new_for_tests(&genesis_config).clock().unix_timestamp;
+        let stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            stake,
+            new_stake_account(
+                10,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    unix_timestamp: epoch_start_timestamp + 50,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..genesis_config
+        };
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+        assert!(calculate_non_circulating_supply(&bank0)
+            .unwrap()
+            .reasons
+            .contains_key(&stake));
+
+        // The lockup expires mid-epoch
+        let bank1 = new_from_parent(bank0);
+        bank1.set_sysvar_for_tests(&Clock {
+            unix_timestamp: epoch_start_timestamp + 100,
+            ..bank1.clock()
+        });
+        assert_eq!(bank1.clock().epoch_start_timestamp, epoch_start_timestamp);
+        let supply = calculate_non_circulating_supply(&bank1).unwrap();
+        assert!(!supply.reasons.contains_key(&stake));
+
+        let epoch_start_supply = non_circulating_supply_epoch_start(&bank1).unwrap();
+        assert_eq!(
+            epoch_start_supply.reasons[&stake],
+            NonCirculatingReason::Lockup
+        );
+        assert_eq!(epoch_start_supply.lamports, supply.lamports + 10);
+    }
+
+    #[test]
+    fn test_stake_state_breakdown() {
+        let stake_states = [
+            StakeStateV2::Uninitialized,
+            StakeStateV2::Initialized(Meta::default()),
+            StakeStateV2::Initialized(Meta::default()),
+            StakeStateV2::Stake(Meta::default(), Stake::default(), StakeFlags::empty()),
+            StakeStateV2::RewardsPool,
+        ];
+        let mut accounts: BTreeMap<Pubkey, Account> = stake_states
+            .iter()
+            .map(|stake_state| {
+                let account = Account::new_data_with_space(
+                    1,
+                    stake_state,
+                    StakeStateV2::size_of(),
+                    &stake::program::id(),
+                )
+                .unwrap();
+                (solana_pubkey::new_rand(), account)
+            })
+            .collect();
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            Account {
+                lamports: 1,
+                data: vec![0xff; 16],
+                owner: stake::program::id(),
+                ..Account::default()
+            },
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let breakdown = stake_state_breakdown(&bank).unwrap();
+        assert_eq!(
+            breakdown,
+            HashMap::from([
+                ("Uninitialized", 1),
+                ("Initialized", 2),
+                ("Stake", 1),
+                ("RewardsPool", 1),
+            ])
+        );
+    }
+
+    #[derive(Debug)]
+    struct PanickingStakeDecoder;
+
+    impl ReasonClassifier for PanickingStakeDecoder {
+        fn classify(
+            &self,
+            _pubkey: &Pubkey,
+            account: &AccountSharedData,
+            _clock: &Clock,
+        ) -> Option<NonCirculatingReason> {
+            if account.lamports() == 13 {
+                panic!("unexpected stake account layout");
+            }
+            None
+        }
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_catch_decode_panics() {
+        let mut accounts = BTreeMap::new();
+        for lamports in [13, 13] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(lamports, &solana_pubkey::new_rand(), Lockup::default()),
+            );
+        }
+        let locked_stake = solana_pubkey::new_rand();
+        accounts.insert(
+            locked_stake,
+            new_stake_account(
+                20,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let expected = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(expected.panicked_decodes, 0);
+
+        for parallel in [false, true] {
+            let config = NonCirculatingSupplyConfig {
+                classifiers: vec![Arc::new(PanickingStakeDecoder)],
+                catch_decode_panics: true,
+                parallel,
+                ..NonCirculatingSupplyConfig::default()
+            };
+            let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+            assert_eq!(supply.panicked_decodes, 2);
+            assert_eq!(supply.lamports, expected.lamports);
+            assert_eq!(supply.reasons[&locked_stake], NonCirculatingReason::Lockup);
+        }
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_activating_stake() {
+        let new_activating_stake_account = |lamports: u64, activation_epoch: Epoch, lockup| {
+            new_delegated_stake_account(
+                lamports,
+                &solana_pubkey::new_rand(),
+                lockup,
+                Delegation {
+                    voter_pubkey: solana_pubkey::new_rand(),
+                    stake: lamports,
+                    activation_epoch,
+                    ..Delegation::default()
+                },
+            )
+        };
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let mut accounts = BTreeMap::new();
+        let activating_stake = solana_pubkey::new_rand();
+        accounts.insert(
+            activating_stake,
+            new_activating_stake_account(30, 0, locked),
+        );
+        // Bootstrap stake is fully effective from genesis
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_activating_stake_account(40, u64::MAX, locked),
+        );
+        // Circulating stake isn't reported, even while activating
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_activating_stake_account(50, 0, Lockup::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(supply.activating_lamports, 0);
+
+        let config = NonCirculatingSupplyConfig {
+            report_activating_stake: true,
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(
+            supply.reasons[&activating_stake],
+            NonCirculatingReason::Lockup
+        );
+        assert_eq!(supply.activating_lamports, 30);
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_non_circulating_supply_for() {
+        let locked_stake = solana_pubkey::new_rand();
+        let authority_stake = solana_pubkey::new_rand();
+        let unlocked_stake = solana_pubkey::new_rand();
+        let system_account = solana_pubkey::new_rand();
+        let unqueried_stake = solana_pubkey::new_rand();
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            locked_stake,
+            new_stake_account(10, &solana_pubkey::new_rand(), locked),
+        );
+        accounts.insert(
+            authority_stake,
+            new_stake_account(20, &withdraw_authority()[0], Lockup::default()),
+        );
+        accounts.insert(
+            unlocked_stake,
+            new_stake_account(30, &solana_pubkey::new_rand(), Lockup::default()),
+        );
+        accounts.insert(system_account, Account::new(40, 0, &Pubkey::default()));
+        accounts.insert(
+            unqueried_stake,
+            new_stake_account(50, &solana_pubkey::new_rand(), locked),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let supply = non_circulating_supply_for(
+            &bank,
+            &[
+                locked_stake,
+                authority_stake,
+                unlocked_stake,
+                system_account,
+                solana_pubkey::new_rand(),
+                // Duplicates are only counted once
+                locked_stake,
+            ],
+        )
+        .unwrap();
+        assert_eq!(supply.lamports, 30);
+        let accounts: HashSet<Pubkey> = supply.accounts.into_iter().collect();
+        assert_eq!(accounts, HashSet::from([locked_stake, authority_stake]));
+        assert_eq!(supply.reasons[&locked_stake], NonCirculatingReason::Lockup);
+        assert_eq!(
+            supply.reasons[&authority_stake],
+            NonCirculatingReason::WithdrawAuthority
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "mainnet-lists"))]
+    fn test_mainnet_lists_disabled() {
+        assert!(non_circulating_accounts().is_empty());
+        assert!(withdraw_authority().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn test_calculate_non_circulating_supply_span() {
+        use {
+            std::sync::Mutex,
+            tracing::{
+                field::{Field, Visit},
+                span, Event, Metadata, Subscriber,
+            },
+        };
+
+        #[derive(Default)]
+        struct RecordedSpan {
+            name: &'static str,
+            fields: BTreeMap<&'static str, String>,
+            entered: bool,
+        }
+
+        impl Visit for RecordedSpan {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                self.fields.insert(field.name(), format!("{value:?}"));
+            }
+        }
+
+        /// Records every span and its fields
+        #[derive(Clone, Default)]
+        struct SpanRecorder(Arc<Mutex<Vec<RecordedSpan>>>);
+
+        impl Subscriber for SpanRecorder {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, attributes: &span::Attributes<'_>) -> span::Id {
+                let mut spans = self.0.lock().unwrap();
+                let mut recorded = RecordedSpan {
+                    name: attributes.metadata().name(),
+                    ..RecordedSpan::default()
+                };
+                attributes.record(&mut recorded);
+                spans.push(recorded);
+                span::Id::from_u64(spans.len() as u64)
+            }
+
+            fn record(&self, id: &span::Id, values: &span::Record<'_>) {
+                values.record(&mut self.0.lock().unwrap()[id.into_u64() as usize - 1]);
+            }
+
+            fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+            fn event(&self, _event: &Event<'_>) {}
+
+            fn enter(&self, id: &span::Id) {
+                self.0.lock().unwrap()[id.into_u64() as usize - 1].entered = true;
+            }
+
+            fn exit(&self, _span: &span::Id) {}
+        }
+
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(
+                10,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let recorder = SpanRecorder::default();
+        let supply = tracing::subscriber::with_default(recorder.clone(), || {
+            calculate_non_circulating_supply(&bank).unwrap()
+        });
+        let spans = recorder.0.lock().unwrap();
+        let span = spans
+            .iter()
+            .find(|span| span.name == "non_circulating_supply")
+            .unwrap();
+        assert!(span.entered);
+        assert_eq!(span.fields["slot"], bank.slot().to_string());
+        assert_eq!(span.fields["accounts"], supply.accounts.len().to_string());
+        assert_eq!(span.fields["lamports"], supply.lamports.to_string());
+        assert!(span.fields.contains_key("duration_us"));
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_scale() {
+        let mut accounts = BTreeMap::new();
+        for lamports in [10, 21] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch: 1,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let unscaled = calculate_non_circulating_supply(&bank).unwrap();
+
+        let config = NonCirculatingSupplyConfig {
+            scale: LamportsScale {
+                numerator: 1,
+                denominator: 2,
+            },
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let scaled = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(scaled.lamports, unscaled.lamports / 2);
+        assert_eq!(scaled.balances, unscaled.balances);
+        assert_eq!(scaled.balances.values().sum::<u64>(), unscaled.lamports);
+
+        assert_eq!(
+            LamportsScale {
+                numerator: u64::MAX,
+                denominator: 1,
+            }
+            .apply(2),
+            u64::MAX
+        );
+        assert_eq!(
+            LamportsScale {
+                numerator: u64::MAX,
+                denominator: u64::MAX,
+            }
+            .apply(u64::MAX),
+            u64::MAX
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_account_count_breakdown() {
+        let mut accounts = BTreeMap::new();
+        for lockup_epoch in [0, 0, 0, 1, 1] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    10,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch: lockup_epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(10, &withdraw_authority()[0], Lockup::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let (non_circulating, circulating_stake) = account_count_breakdown(&bank).unwrap();
+        assert_eq!(non_circulating, non_circulating_accounts().len() + 3);
+        assert_eq!(circulating_stake, 3);
+        assert_eq!(
+            non_circulating + circulating_stake,
+            load_stake_accounts(&bank).unwrap().len() + non_circulating_accounts().len()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_partition_stake_accounts() {
+        let mut accounts = BTreeMap::new();
+        for lockup_epoch in [0, 0, 1, 1, 1] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    10,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch: lockup_epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(10, &withdraw_authority()[0], Lockup::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let (non_circulating, circulating) = partition_stake_accounts(&bank).unwrap();
+        assert_eq!((non_circulating.len(), circulating.len()), (4, 2));
+        let non_circulating: HashSet<Pubkey> = non_circulating.into_iter().collect();
+        let circulating: HashSet<Pubkey> = circulating.into_iter().collect();
+        assert!(non_circulating.is_disjoint(&circulating));
+        let scanned: HashSet<Pubkey> = load_stake_accounts(&bank)
+            .unwrap()
+            .into_iter()
+            .map(|(pubkey, _account)| pubkey)
+            .collect();
+        assert_eq!(&non_circulating | &circulating, scanned);
+    }
+
+    #[test]
+    fn test_top_k_non_circulating() {
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let mut accounts = BTreeMap::new();
+        let mut locked_stakes = vec![];
+        for lamports in 1..=10 {
+            let pubkey = solana_pubkey::new_rand();
+            accounts.insert(
+                pubkey,
+                new_stake_account(lamports, &solana_pubkey::new_rand(), locked),
+            );
+            locked_stakes.push((pubkey, lamports));
+        }
+        // Circulating, so never part of the top K however large
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(1_000, &solana_pubkey::new_rand(), Lockup::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let top = top_k_non_circulating(&bank, 3).unwrap();
+        locked_stakes.reverse();
+        assert_eq!(top, locked_stakes[..3]);
+        assert!(top_k_non_circulating(&bank, 0).unwrap().is_empty());
+        // Nothing is preallocated from `k`
+        let all = top_k_non_circulating(&bank, usize::MAX).unwrap();
+        assert_eq!(all[..locked_stakes.len()], locked_stakes);
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_authority_lockup_report() {
+        let authority = withdraw_authority()[0];
+        let unlocked_stake = solana_pubkey::new_rand();
+        let locked_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            unlocked_stake,
+            new_stake_account(10, &authority, Lockup::default()),
+        );
+        accounts.insert(
+            locked_stake,
+            new_stake_account(
+                20,
+                &authority,
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        // Locked up, but not controlled by a hardcoded authority
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(
+                30,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        assert_eq!(
+            authority_lockup_report(&bank).unwrap(),
+            AuthorityLockupReport {
+                without_lockup: vec![unlocked_stake],
+                without_lockup_lamports: 10,
+                with_lockup: vec![locked_stake],
+                with_lockup_lamports: 20,
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_permanently_non_circulating() {
+        let authority = withdraw_authority()[0];
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let mut accounts = BTreeMap::new();
+        for (lamports, withdrawer, lockup) in [
+            // Authority only
+            (10, authority, Lockup::default()),
+            // Authority and time lockup
+            (20, authority, locked),
+            // Time lockup only
+            (40, solana_pubkey::new_rand(), locked),
+        ] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(lamports, &withdrawer, lockup),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert_eq!(permanently_non_circulating(&bank).unwrap(), 10);
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_hardcoded_stake_accounts() {
+        let hardcoded = non_circulating_accounts()[0];
+        let (genesis_config, _mint_keypair) = create_genesis_config(500);
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert!(hardcoded_stake_accounts(&bank).is_empty());
+
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            hardcoded,
+            new_stake_account(10, &solana_pubkey::new_rand(), Lockup::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert_eq!(hardcoded_stake_accounts(&bank), vec![hardcoded]);
+    }
+
+    #[test]
+    fn test_clock_at_epoch() {
+        let mut accounts = BTreeMap::new();
+        let mut locked_until = vec![];
+        for lockup_epoch in 1..=3 {
+            let pubkey = solana_pubkey::new_rand();
+            accounts.insert(
+                pubkey,
+                new_stake_account(
+                    10,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch: lockup_epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+            locked_until.push((pubkey, lockup_epoch));
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        for epoch in 0..=3 {
+            let clock = clock_at_epoch(&bank, epoch);
+            assert_eq!(clock.epoch, epoch);
+            assert_eq!(bank.epoch_schedule().get_epoch(clock.slot), epoch);
+            assert!(clock.unix_timestamp >= bank.clock().unix_timestamp);
+
+            let config = NonCirculatingSupplyConfig {
+                clock: Some(clock),
+                ..NonCirculatingSupplyConfig::default()
+            };
+            let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+            for (pubkey, lockup_epoch) in locked_until.iter() {
+                assert_eq!(supply.reasons.contains_key(pubkey), *lockup_epoch > epoch);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_protocol_fees_as_circulating() {
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            PROTOCOL_FEE_ACCOUNT,
+            Account::new(1_000, 0, &Pubkey::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(
+            supply.reasons[&PROTOCOL_FEE_ACCOUNT],
+            NonCirculatingReason::Hardcoded
+        );
+        assert_eq!(supply.balances[&PROTOCOL_FEE_ACCOUNT], 1_000);
+
+        let config = NonCirculatingSupplyConfig {
+            protocol_fees_as_circulating: true,
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let circulating_fees =
+            calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert!(!circulating_fees.reasons.contains_key(&PROTOCOL_FEE_ACCOUNT));
+        assert!(!circulating_fees.accounts.contains(&PROTOCOL_FEE_ACCOUNT));
+        assert_eq!(circulating_fees.lamports, supply.lamports - 1_000);
+    }
+
+    #[test]
+    fn test_require_hardcoded_present() {
+        let present = solana_pubkey::new_rand();
+        let missing = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        for pubkey in non_circulating_accounts().into_iter().chain([present]) {
+            accounts.insert(pubkey, Account::new(1_000, 0, &Pubkey::default()));
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let mut config = NonCirculatingSupplyConfig {
+            additional_non_circulating_accounts: vec![present],
+            require_hardcoded_present: true,
+            ..NonCirculatingSupplyConfig::default()
+        };
+        assert!(calculate_non_circulating_supply_with_config(&bank, &config).is_ok());
+
+        config.additional_non_circulating_accounts.push(missing);
+        let err = calculate_non_circulating_supply_with_config(&bank, &config).unwrap_err();
+        assert!(err.to_string().contains(&missing.to_string()), "{err}");
+        config.require_hardcoded_present = false;
+        assert!(calculate_non_circulating_supply_with_config(&bank, &config).is_ok());
+    }
+
+    #[test]
+    fn test_delegated_stake_only() {
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let initialized = solana_pubkey::new_rand();
+        let delegated = solana_pubkey::new_rand();
+        let mut delegated_account =
+            AccountSharedData::from(new_stake_account(20, &solana_pubkey::new_rand(), locked));
+        let meta = stake_state::meta_from(&delegated_account).unwrap();
+        delegated_account
+            .serialize_data(&StakeStateV2::Stake(
+                meta,
+                Stake::default(),
+                StakeFlags::empty(),
+            ))
+            .unwrap();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            initialized,
+            new_stake_account(10, &solana_pubkey::new_rand(), locked),
+        );
+        accounts.insert(delegated, Account::from(delegated_account));
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert!(supply.reasons.contains_key(&initialized));
+        assert!(supply.reasons.contains_key(&delegated));
+
+        let config = NonCirculatingSupplyConfig {
+            delegated_stake_only: true,
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let delegated_supply =
+            calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert!(!delegated_supply.reasons.contains_key(&initialized));
+        assert_eq!(
+            delegated_supply.reasons[&delegated],
+            NonCirculatingReason::Lockup
+        );
+        assert_eq!(delegated_supply.lamports, supply.lamports - 10);
+    }
+
+    #[test]
+    fn test_as_of_unix() {
+        let genesis_config = GenesisConfig::default();
+        let now = Bank::new_for_tests(&genesis_config).clock().unix_timestamp;
+        let lockup_timestamp = now - 100;
+        let stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            stake,
+            new_stake_account(
+                10,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    unix_timestamp: lockup_timestamp,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..genesis_config
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        // The lockup has expired by the bank's clock
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert!(!supply.reasons.contains_key(&stake));
+
+        let supply_as_of = |as_of_unix| {
+            let config = NonCirculatingSupplyConfig {
+                as_of_unix: Some(as_of_unix),
+                ..NonCirculatingSupplyConfig::default()
+            };
+            calculate_non_circulating_supply_with_config(&bank, &config).unwrap()
+        };
+        let supply = supply_as_of(lockup_timestamp - 1);
+        assert_eq!(supply.reasons[&stake], NonCirculatingReason::Lockup);
+        let supply = supply_as_of(lockup_timestamp);
+        assert!(!supply.reasons.contains_key(&stake));
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_verify_reason_partition() {
+        let hardcoded = non_circulating_accounts()[0];
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let mut accounts = BTreeMap::new();
+        // Hardcoded, locked up and controlled by a hardcoded authority
+        accounts.insert(
+            hardcoded,
+            new_stake_account(10, &withdraw_authority()[0], locked),
+        );
+        // Locked up and controlled by a hardcoded authority
+        let locked_authority_stake = solana_pubkey::new_rand();
+        accounts.insert(
+            locked_authority_stake,
+            new_stake_account(20, &withdraw_authority()[1], locked),
+        );
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(30, &solana_pubkey::new_rand(), locked),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let config = NonCirculatingSupplyConfig {
+            verify_reason_partition: true,
+            consolidate_dust_below: Some(25),
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(supply.reasons[&hardcoded], NonCirculatingReason::Hardcoded);
+        assert_eq!(
+            supply.reasons[&locked_authority_stake],
+            NonCirculatingReason::Lockup
+        );
+        assert_eq!(
+            supply.lamports_by_reason().values().sum::<u64>(),
+            supply.lamports
+        );
+
+        let mut duplicated = supply.clone();
+        let listed = duplicated.accounts[0];
+        duplicated.accounts.push(listed);
+        assert!(duplicated.verify_reason_partition().is_err());
+
+        let mut unbalanced = supply.clone();
+        unbalanced.balances.remove(&locked_authority_stake);
+        assert!(unbalanced.verify_reason_partition().is_err());
+
+        let mut miscounted = supply;
+        miscounted.lamports += 1;
+        assert!(miscounted.verify_reason_partition().is_err());
+    }
+
+    /// Creates a bank in which `locked_lamports` of stake is non-circulating and `circulating`
+    /// lamports are held by a system account, on top of the genesis sysvars and builtins
+    fn new_bank_with_locked_stake(locked_lamports: u64, circulating: u64) -> Bank {
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(
+                locked_lamports,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            Account::new(circulating, 0, &Pubkey::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        Bank::new_for_tests(&genesis_config)
+    }
+
+    #[test]
+    fn test_circulating_share_pct() {
+        let genesis_lamports = genesis_sysvar_and_builtin_program_lamports();
+
+        // A quarter of the supply is locked
+        let bank = new_bank_with_locked_stake(genesis_lamports, 2 * genesis_lamports);
+        assert_eq!(circulating_share_pct(&bank, 2).unwrap(), 75.0);
+        assert_eq!(circulating_share_pct(&bank, 0).unwrap(), 75.0);
+
+        // A third of the supply is locked
+        let bank = new_bank_with_locked_stake(genesis_lamports, genesis_lamports);
+        assert_eq!(circulating_share_pct(&bank, 2).unwrap(), 66.67);
+        assert_eq!(circulating_share_pct(&bank, 0).unwrap(), 67.0);
+    }
+
+    #[test]
+    fn test_effective_circulating_supply() {
+        let locked_stake = solana_pubkey::new_rand();
+        let burn_address = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            locked_stake,
+            new_stake_account(
+                500,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        accounts.insert(burn_address, Account::new(100, 0, &Pubkey::default()));
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(supply.balances[&locked_stake], 500);
+
+        let expected = bank.capitalization() - supply.lamports - 100;
+        assert_eq!(
+            effective_circulating_supply(&bank, &[burn_address]).unwrap(),
+            expected
+        );
+        // Listed twice, or already non-circulating: subtracted once
+        assert_eq!(
+            effective_circulating_supply(&bank, &[burn_address, burn_address, locked_stake])
+                .unwrap(),
+            expected
+        );
+        assert_eq!(
+            effective_circulating_supply(&bank, &[]).unwrap(),
+            expected + 100
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_hardcoded_non_circulating_lamports() {
+        let mut accounts = BTreeMap::new();
+        for (index, pubkey) in non_circulating_accounts().into_iter().enumerate() {
+            accounts.insert(
+                pubkey,
+                Account::new(100 + index as u64, 0, &Pubkey::default()),
+            );
+        }
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            Account::new(1_000, 0, &Pubkey::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(
+            hardcoded_non_circulating_lamports(&bank),
+            supply.lamports_by_reason()[&NonCirculatingReason::Hardcoded]
+        );
+        assert_eq!(hardcoded_non_circulating_lamports(&bank), supply.lamports);
+    }
+
+    #[test]
+    fn test_non_circulating_supply_display() {
+        let accounts: Vec<Pubkey> = (0..3).map(|_| solana_pubkey::new_rand()).collect();
+        let reasons = accounts
+            .iter()
+            .map(|pubkey| (*pubkey, NonCirculatingReason::Lockup))
+            .collect();
+        let mut supply = NonCirculatingSupply::new_for_tests(1_500, accounts, Some(reasons), None);
+        supply.epoch = 4;
+        assert_eq!(
+            supply.to_string(),
+            "1500 non-circulating lamports in 3 accounts at epoch 4"
+        );
+
+        supply.dust = Some(ConsolidatedDust {
+            lamports: 20,
+            count: 2,
+        });
+        assert_eq!(
+            supply.to_string(),
+            "1500 non-circulating lamports in 3 accounts at epoch 4, including 20 lamports in 2 \
+             dust accounts"
+        );
+    }
+
+    #[test]
+    fn test_percentage_string() {
+        let supply = NonCirculatingSupply::new_for_tests(1, vec![], None, None);
+        assert_eq!(supply.percentage_string(8, 2), "12.50%");
+        assert_eq!(supply.percentage_string(3, 3), "33.333%");
+        assert_eq!(supply.percentage_string(0, 2), "0.00%");
+    }
+
+    #[test]
+    fn test_estimated_size_bytes() {
+        let new_supply = |count: usize| {
+            let accounts: Vec<Pubkey> = (0..count).map(|_| solana_pubkey::new_rand()).collect();
+            let reasons = accounts
+                .iter()
+                .map(|pubkey| (*pubkey, NonCirculatingReason::Lockup))
+                .collect();
+            let balances = accounts.iter().map(|pubkey| (*pubkey, 1)).collect();
+            NonCirculatingSupply::new_for_tests(
+                count as u64,
+                accounts,
+                Some(reasons),
+                Some(balances),
+            )
+        };
+        let empty = new_supply(0).estimated_size_bytes();
+        let small = new_supply(10).estimated_size_bytes();
+        let large = new_supply(1_000).estimated_size_bytes();
+        assert_eq!(empty, mem::size_of::<NonCirculatingSupply>());
+        assert!(small > empty);
+        assert!(large > small);
+        // At least the pubkeys of the accounts list and both maps
+        assert!(large >= 1_000 * 3 * mem::size_of::<Pubkey>());
+    }
+
+    #[test]
+    fn test_projections_with_warmup_epoch_schedule() {
+        let stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            stake,
+            new_stake_account(
+                10,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 2,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        // Epochs 0 and 1 are shortened to 32 and 64 slots by the warmup
+        let genesis_config = GenesisConfig {
+            accounts,
+            epoch_schedule: EpochSchedule::custom(128, 128, true),
+            ..GenesisConfig::default()
+        };
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+        assert_eq!(unlock_schedule(&bank0).unwrap(), BTreeMap::from([(2, 10)]));
+        assert_eq!(clock_at_epoch(&bank0, 1).slot, 32);
+        assert_eq!(clock_at_epoch(&bank0, 2).slot, 96);
+        assert!(next_epoch_projection(&bank0)
+            .unwrap()
+            .reasons
+            .contains_key(&stake));
+
+        let bank1 = Arc::new(Bank::new_from_parent(bank0, &Pubkey::default(), 32));
+        assert_eq!(bank1.epoch(), 1);
+        assert!(calculate_non_circulating_supply(&bank1)
+            .unwrap()
+            .reasons
+            .contains_key(&stake));
+        let projection = next_epoch_projection(&bank1).unwrap();
+        assert_eq!(projection.epoch, 1);
+        assert!(!projection.reasons.contains_key(&stake));
+
+        // The lockup expires at the first slot of epoch 2, not after a full-length epoch 1
+        let bank2 = Bank::new_from_parent(bank1, &Pubkey::default(), 96);
+        assert_eq!(bank2.epoch(), 2);
+        assert!(!calculate_non_circulating_supply(&bank2)
+            .unwrap()
+            .reasons
+            .contains_key(&stake));
+    }
+
+    #[test]
+    fn test_non_circulating_delegated_to() {
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let voter_a = solana_pubkey::new_rand();
+        let voter_b = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        for (lamports, voter, lockup) in [
+            (10, &voter_a, locked),
+            (20, &voter_a, locked),
+            (40, &voter_a, Lockup::default()),
+            (80, &voter_b, locked),
+            (160, &voter_b, Lockup::default()),
+        ] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_delegated_stake_account(
+                    lamports,
+                    &solana_pubkey::new_rand(),
+                    lockup,
+                    Delegation {
+                        voter_pubkey: *voter,
+                        stake: lamports,
+                        ..Delegation::default()
+                    },
+                ),
+            );
+        }
+        // Locked, but not delegated
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(320, &solana_pubkey::new_rand(), locked),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        assert_eq!(non_circulating_delegated_to(&bank, &voter_a).unwrap(), 30);
+        assert_eq!(non_circulating_delegated_to(&bank, &voter_b).unwrap(), 80);
+        assert_eq!(
+            non_circulating_delegated_to(&bank, &solana_pubkey::new_rand()).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_exclude_stake_accounts() {
+        const MIGRATED_FLAG_OFFSET: usize = StakeStateV2::size_of();
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let new_flagged_stake_account = |lamports: u64, flag: u8| {
+            let mut account = new_stake_account(lamports, &solana_pubkey::new_rand(), locked);
+            account.data.push(flag);
+            account
+        };
+        let migrated_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(migrated_stake, new_flagged_stake_account(10, 1));
+        accounts.insert(solana_pubkey::new_rand(), new_flagged_stake_account(20, 0));
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(40, &solana_pubkey::new_rand(), locked),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let unfiltered = calculate_non_circulating_supply(&bank).unwrap();
+        assert!(unfiltered.reasons.contains_key(&migrated_stake));
+
+        let config = NonCirculatingSupplyConfig {
+            exclude_stake_accounts: Some(StakeAccountPredicate::new(|_pubkey, account| {
+                account.data().get(MIGRATED_FLAG_OFFSET) == Some(&1)
+            })),
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert!(!supply.reasons.contains_key(&migrated_stake));
+        assert_eq!(supply.lamports, unfiltered.lamports - 10);
+    }
+
+    #[test]
+    fn test_account_rule_precedence() {
+        const FLAG_OFFSET: usize = StakeStateV2::size_of();
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        // Flag 1 matches the exclude predicate, flag 2 the include predicate, flag 3 both
+        let new_flagged_stake_account = |lamports: u64, lockup: Lockup, flag: u8| {
+            let mut account = new_stake_account(lamports, &solana_pubkey::new_rand(), lockup);
+            account.data.push(flag);
+            account
+        };
+        let excluded_and_allowed = solana_pubkey::new_rand();
+        let excluded_and_denied = solana_pubkey::new_rand();
+        let denied_and_allowed = solana_pubkey::new_rand();
+        let denied_and_included = solana_pubkey::new_rand();
+        let excluded_and_included = solana_pubkey::new_rand();
+        let exclude_and_include_predicates = solana_pubkey::new_rand();
+        let included = solana_pubkey::new_rand();
+        let locked_default = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        for pubkey in [
+            excluded_and_allowed,
+            excluded_and_denied,
+            denied_and_allowed,
+        ] {
+            accounts.insert(pubkey, Account::new(1, 0, &Pubkey::default()));
+        }
+        accounts.insert(
+            denied_and_included,
+            new_flagged_stake_account(10, Lockup::default(), 2),
+        );
+        accounts.insert(
+            excluded_and_included,
+            new_flagged_stake_account(20, Lockup::default(), 2),
+        );
+        accounts.insert(
+            exclude_and_include_predicates,
+            new_flagged_stake_account(40, locked, 3),
+        );
+        accounts.insert(
+            included,
+            new_flagged_stake_account(80, Lockup::default(), 2),
+        );
+        accounts.insert(locked_default, new_flagged_stake_account(160, locked, 0));
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let baseline = calculate_non_circulating_supply(&bank).unwrap();
+
+        let config = NonCirculatingSupplyConfig {
+            excluded_accounts: vec![
+                excluded_and_allowed,
+                excluded_and_denied,
+                excluded_and_included,
+            ],
+            denied_accounts: vec![excluded_and_denied, denied_and_allowed, denied_and_included],
+            additional_non_circulating_accounts: vec![excluded_and_allowed, denied_and_allowed],
+            exclude_stake_accounts: Some(StakeAccountPredicate::new(|_pubkey, account| {
+                matches!(account.data().get(FLAG_OFFSET), Some(1 | 3))
+            })),
+            include_stake_accounts: Some(StakeAccountPredicate::new(|_pubkey, account| {
+                matches!(account.data().get(FLAG_OFFSET), Some(2 | 3))
+            })),
+            ..NonCirculatingSupplyConfig::default()
+        };
+        assert_eq!(
+            config.account_rule(&excluded_and_denied, None),
+            AccountRule::Excluded
+        );
+        assert_eq!(
+            config.account_rule(&denied_and_allowed, None),
+            AccountRule::Denied
+        );
+        assert_eq!(
+            config.account_rule(&locked_default, None),
+            AccountRule::Default
+        );
+
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        for pubkey in [
+            excluded_and_allowed,
+            excluded_and_denied,
+            denied_and_allowed,
+            denied_and_included,
+            excluded_and_included,
+            exclude_and_include_predicates,
+        ] {
+            assert!(!supply.reasons.contains_key(&pubkey), "{pubkey}");
+        }
+        assert_eq!(
+            supply.reasons[&included],
+            NonCirculatingReason::Custom(AccountRule::INCLUDED_REASON)
+        );
+        assert_eq!(
+            supply.reasons[&locked_default],
+            NonCirculatingReason::Lockup
+        );
+        // The locked account matching both predicates is denied, and the unlocked one allowed
+        assert_eq!(supply.lamports, baseline.lamports - 40 + 80);
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_classify_prefiltered() {
+        let authority = withdraw_authority()[0];
+        let mut accounts = BTreeMap::new();
+        for (lamports, withdrawer, epoch) in [
+            (10, solana_pubkey::new_rand(), 1),
+            (20, authority, 0),
+            (40, solana_pubkey::new_rand(), 0),
+            (80, solana_pubkey::new_rand(), 2),
+        ] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    &withdrawer,
+                    Lockup {
+                        epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let full = calculate_non_circulating_supply(&bank).unwrap();
 
-    for key in non_circulating_accounts() {
-        non_circulating_accounts_set.insert(key);
+        let stake_accounts = load_stake_accounts(&bank).unwrap();
+        let prefiltered = classify_prefiltered(
+            &stake_accounts,
+            &bank.clock(),
+            &NonCirculatingSupplyConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            prefiltered.lamports,
+            full.lamports - hardcoded_non_circulating_lamports(&bank)
+        );
+        assert_eq!(prefiltered.accounts.len(), 3);
+        for (pubkey, reason) in prefiltered.reasons.iter() {
+            assert_eq!(full.reasons[pubkey], *reason);
+            assert_eq!(full.balances[pubkey], prefiltered.balances[pubkey]);
+        }
+        for (pubkey, reason) in full.reasons.iter() {
+            if *reason != NonCirculatingReason::Hardcoded {
+                assert!(prefiltered.reasons.contains_key(pubkey));
+            }
+        }
     }
-    let withdraw_authority_list = withdraw_authority();
 
-    let clock = bank.clock();
-    let config = &ScanConfig::default();
-    let stake_accounts = if bank
-        .rc
-        .accounts
-        .accounts_db
-        .account_indexes
-        .contains(&AccountIndex::ProgramId)
-    {
-        bank.get_filtered_indexed_accounts(
-            &IndexKey::ProgramId(stake::program::id()),
-            // The program-id account index checks for Account owner on inclusion. However, due to
-            // the current AccountsDb implementation, an account may remain in storage as a
-            // zero-lamport Account::Default() after being wiped and reinitialized in later
-            // updates. We include the redundant filter here to avoid returning these accounts.
-            |account| account.owner() == &stake::program::id(),
-            config,
-            None,
-        )?
-    } else {
-        bank.get_program_accounts(&stake::program::id(), config)?
-    };
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_static_and_dynamic_lamports() {
+        let treasury = solana_pubkey::new_rand();
+        let authority = solana_pubkey::new_rand();
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let mut accounts = BTreeMap::new();
+        for pubkey in non_circulating_accounts() {
+            accounts.insert(pubkey, Account::new(100, 0, &Pubkey::default()));
+        }
+        accounts.insert(treasury, Account::new(1_000, 0, &Pubkey::default()));
+        for (lamports, withdrawer, lockup) in [
+            (10, solana_pubkey::new_rand(), locked),
+            (20, withdraw_authority()[0], Lockup::default()),
+            (40, authority, Lockup::default()),
+        ] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(lamports, &withdrawer, lockup),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let hardcoded_lamports = 100 * non_circulating_accounts().len() as u64;
 
-    for (pubkey, account) in stake_accounts.iter() {
-        let stake_account = stake_state::from(account).unwrap_or_default();
-        match stake_account {
-            StakeStateV2::Initialized(meta) => {
-                if meta.lockup.is_in_force(&clock, None)
-                    || withdraw_authority_list.contains(&meta.authorized.withdrawer)
-                {
-                    non_circulating_accounts_set.insert(*pubkey);
-                }
-            }
-            StakeStateV2::Stake(meta, _stake, _stake_flags) => {
-                if meta.lockup.is_in_force(&clock, None)
-                    || withdraw_authority_list.contains(&meta.authorized.withdrawer)
-                {
-                    non_circulating_accounts_set.insert(*pubkey);
-                }
-            }
-            _ => {}
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(supply.static_lamports, hardcoded_lamports + 20);
+        assert_eq!(supply.dynamic_lamports, 0);
+
+        let config = NonCirculatingSupplyConfig {
+            additional_non_circulating_accounts: vec![treasury],
+            additional_withdraw_authorities: vec![authority],
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(supply.static_lamports, hardcoded_lamports + 20);
+        assert_eq!(supply.dynamic_lamports, 1_000 + 40);
+        // The locked stake is attributed to neither
+        assert_eq!(
+            supply.lamports,
+            supply.static_lamports + supply.dynamic_lamports + 10
+        );
+    }
+
+    #[test]
+    fn test_calculate_non_circulating_supply_and_scanned_stake_accounts() {
+        let mut accounts = BTreeMap::new();
+        for (lamports, epoch) in [(10, 1), (20, 0), (40, 2), (80, 0)] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
         }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let (supply, scanned) = calculate_non_circulating_supply_and_scanned_stake_accounts(
+            &bank,
+            &NonCirculatingSupplyConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            supply.lamports,
+            calculate_non_circulating_supply(&bank).unwrap().lamports
+        );
+
+        let scanned: HashSet<Pubkey> = scanned.into_iter().collect();
+        let stake_accounts: HashSet<Pubkey> = load_stake_accounts(&bank)
+            .unwrap()
+            .into_iter()
+            .map(|(pubkey, _account)| pubkey)
+            .collect();
+        assert_eq!(scanned, stake_accounts);
+        let non_circulating_stake: Vec<&Pubkey> = supply
+            .reasons
+            .iter()
+            .filter(|(_pubkey, reason)| **reason != NonCirculatingReason::Hardcoded)
+            .map(|(pubkey, _reason)| pubkey)
+            .collect();
+        assert_eq!(non_circulating_stake.len(), 2);
+        assert!(non_circulating_stake
+            .iter()
+            .all(|pubkey| scanned.contains(*pubkey)));
     }
 
-    let lamports = non_circulating_accounts_set
-        .iter()
-        .map(|pubkey| bank.get_balance(pubkey))
-        .sum();
+    #[test]
+    fn test_classify_prefiltered_skips_misowned_accounts() {
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let stake = solana_pubkey::new_rand();
+        let system_owned = solana_pubkey::new_rand();
+        let mut system_owned_account: AccountSharedData =
+            new_stake_account(20, &solana_pubkey::new_rand(), locked).into();
+        system_owned_account.set_owner(solana_sdk_ids::system_program::id());
+        let accounts = vec![
+            (
+                stake,
+                new_stake_account(10, &solana_pubkey::new_rand(), locked).into(),
+            ),
+            (system_owned, system_owned_account.clone()),
+        ];
+        let supply = classify_prefiltered(
+            &accounts,
+            &Clock::default(),
+            &NonCirculatingSupplyConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(supply.lamports, 10);
+        assert_eq!(supply.reasons[&stake], NonCirculatingReason::Lockup);
+        assert!(!supply.reasons.contains_key(&system_owned));
+        assert_eq!(supply.misowned_accounts, vec![system_owned]);
+        assert!(supply.undecodable_accounts.is_empty());
+        assert_eq!(
+            stake_account_reason(&system_owned_account, &Clock::default(), &[]),
+            None
+        );
+    }
 
-    Ok(NonCirculatingSupply {
-        lamports,
-        accounts: non_circulating_accounts_set.into_iter().collect(),
-    })
-}
+    #[test]
+    fn test_calculate_non_circulating_supply_with_progress() {
+        let mut accounts = BTreeMap::new();
+        for (lamports, epoch) in [(10, 1), (20, 0), (40, 2)] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let expected = calculate_non_circulating_supply(&bank).unwrap();
 
-// Mainnet-beta accounts that should be considered non-circulating
-pub fn non_circulating_accounts() -> Vec<Pubkey> {
-    [
-        solana_pubkey::pubkey!("11111111111111111111111111111112"),
-    ]
-    .into()
-}
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let supply = calculate_non_circulating_supply_with_progress(
+            &bank,
+            &NonCirculatingSupplyConfig::default(),
+            sender,
+        )
+        .unwrap();
+        assert_eq!(supply.lamports, expected.lamports);
+        assert_eq!(supply.reasons, expected.reasons);
 
-// Withdraw authority for autostaked accounts on mainnet-beta
-pub fn withdraw_authority() -> Vec<Pubkey> {
-    [
-        solana_pubkey::pubkey!("8CUUMKYNGxdgYio5CLHRHyzMEhhVRMcqefgE6dLqnVRK"),
-        solana_pubkey::pubkey!("3FFaheyqtyAXZSYxDzsr5CVKvJuvZD1WE1VEsBtDbRqB"),
-        solana_pubkey::pubkey!("FdGYQdiRky8NZzN9wZtczTBcWLYYRXrJ3LMDhqDPn5rM"),
-        solana_pubkey::pubkey!("4e6KwQpyzGQPfgVr5Jn3g5jLjbXB4pKPa2jRLohEb1QA"),
-        solana_pubkey::pubkey!("FjiEiVKyMGzSLpqoB27QypukUfyWHrwzPcGNtopzZVdh"),
-        solana_pubkey::pubkey!("DwbVjia1mYeSGoJipzhaf4L5hfer2DJ1Ys681VzQm5YY"),
-        solana_pubkey::pubkey!("GeMGyvsTEsANVvcT5cme65Xq5MVU8fVVzMQ13KAZFNS2"),
-        solana_pubkey::pubkey!("Bj3aQ2oFnZYfNR1njzRjmWizzuhvfcYLckh76cqsbuBM"),
-        solana_pubkey::pubkey!("4ZJhPQAgUseCsWhKvJLTmmRRUV74fdoTpQLNfKoekbPY"),
-        solana_pubkey::pubkey!("HXdYQ5gixrY2H6Y9gqsD8kPM2JQKSaRiohDQtLbZkRWE"),
-    ]
-    .into()
-}
+        let progress: Vec<ScanProgress> = receiver.iter().collect();
+        assert!(!progress.is_empty());
+        assert_eq!(
+            progress.last(),
+            Some(&ScanProgress {
+                scanned_accounts: load_stake_accounts(&bank).unwrap().len(),
+                matched_accounts: expected.reasons.len(),
+            })
+        );
+    }
 
-#[cfg(test)]
-mod tests {
-    use {
-        super::*,
-        crate::genesis_utils::genesis_sysvar_and_builtin_program_lamports,
-        solana_account::{Account, AccountSharedData},
-        solana_cluster_type::ClusterType,
-        solana_epoch_schedule::EpochSchedule,
-        solana_genesis_config::GenesisConfig,
-        solana_stake_interface::state::{Authorized, Lockup, Meta},
-        std::{collections::BTreeMap, sync::Arc},
-    };
+    #[test]
+    fn test_progress_during_scan() {
+        let mut accounts = BTreeMap::new();
+        for lamports in 1..=5 {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch: 1,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert_eq!(load_stake_accounts(&bank).unwrap().len(), 5);
+        let hardcoded = non_circulating_accounts().len();
 
-    fn new_from_parent(parent: Arc<Bank>) -> Bank {
-        let slot = parent.slot() + 1;
-        let collector_id = Pubkey::default();
-        Bank::new_from_parent(parent, &collector_id, slot)
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let options = ScanOptions {
+            progress: Some((&sender, 2)),
+            ..ScanOptions::default()
+        };
+        calculate_non_circulating_supply_impl(
+            &bank,
+            &NonCirculatingSupplyConfig::default(),
+            options,
+        )
+        .unwrap();
+        drop(sender);
+        let progress: Vec<ScanProgress> = receiver.iter().collect();
+        let expected: Vec<ScanProgress> = [
+            // While loading
+            (2, hardcoded),
+            (4, hardcoded),
+            // While classifying
+            (5, hardcoded + 2),
+            (5, hardcoded + 4),
+            // Once complete
+            (5, hardcoded + 5),
+        ]
+        .into_iter()
+        .map(|(scanned_accounts, matched_accounts)| ScanProgress {
+            scanned_accounts,
+            matched_accounts,
+        })
+        .collect();
+        assert_eq!(progress, expected);
     }
 
     #[test]
-    fn test_calculate_non_circulating_supply() {
-        let mut accounts: BTreeMap<Pubkey, Account> = BTreeMap::new();
-        let balance = 10;
-        let num_genesis_accounts = 10;
-        for _ in 0..num_genesis_accounts {
+    #[cfg(feature = "mainnet-lists")]
+    fn test_lockup_vs_authority_ratio() {
+        let authority = withdraw_authority()[0];
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let mut accounts = BTreeMap::new();
+        for (lamports, withdrawer, lockup) in [
+            (10, solana_pubkey::new_rand(), locked),
+            (20, authority, Lockup::default()),
+            // Both locked up and controlled by a hardcoded authority
+            (40, authority, locked),
+            (80, solana_pubkey::new_rand(), Lockup::default()),
+        ] {
             accounts.insert(
                 solana_pubkey::new_rand(),
-                Account::new(balance, 0, &Pubkey::default()),
+                new_stake_account(lamports, &withdrawer, lockup),
             );
         }
-        let non_circulating_accounts = non_circulating_accounts();
-        let num_non_circulating_accounts = non_circulating_accounts.len() as u64;
-        for key in non_circulating_accounts.clone() {
-            accounts.insert(key, Account::new(balance, 0, &Pubkey::default()));
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        assert_eq!(lockup_vs_authority_ratio(&bank).unwrap(), (50, 20));
+    }
+
+    #[test]
+    fn test_average_lockup_remaining() {
+        let mut accounts = BTreeMap::new();
+        for (lamports, epoch) in [(100, 5), (100, 15), (1_000, 0)] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
         }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert_eq!(average_lockup_remaining(&bank).unwrap(), 10.0);
 
-        let num_stake_accounts = 3;
-        for _ in 0..num_stake_accounts {
-            let pubkey = solana_pubkey::new_rand();
-            let meta = Meta {
-                authorized: Authorized::auto(&pubkey),
-                lockup: Lockup {
+        let bank = Bank::new_for_tests(&GenesisConfig::default());
+        assert_eq!(average_lockup_remaining(&bank).unwrap(), 0.0);
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_supply_reconciliation_gap() {
+        let locked = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let mut accounts = BTreeMap::new();
+        for pubkey in non_circulating_accounts() {
+            accounts.insert(pubkey, Account::new(100, 0, &Pubkey::default()));
+        }
+        for (lamports, withdrawer, lockup) in [
+            (10, solana_pubkey::new_rand(), locked),
+            (20, withdraw_authority()[0], Lockup::default()),
+            (40, solana_pubkey::new_rand(), Lockup::default()),
+        ] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(lamports, &withdrawer, lockup),
+            );
+        }
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            Account::new(1_000, 0, &Pubkey::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Arc::new(Bank::new_for_tests(&genesis_config));
+        assert_eq!(supply_reconciliation_gap(&bank).unwrap(), 0);
+
+        // Lamports minted without updating the capitalization are caught
+        let bank = new_from_parent(bank);
+        bank.store_account(
+            &solana_pubkey::new_rand(),
+            &AccountSharedData::new(500, 0, &Pubkey::default()),
+        );
+        assert_eq!(supply_reconciliation_gap(&bank).unwrap(), -500);
+    }
+
+    #[test]
+    #[cfg(feature = "mainnet-lists")]
+    fn test_custodian_liftable_accounts() {
+        let custodian = solana_pubkey::new_rand();
+        let liftable_stake = solana_pubkey::new_rand();
+        let time_locked_stake = solana_pubkey::new_rand();
+        let authority_stake = solana_pubkey::new_rand();
+        let expired_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        for (pubkey, withdrawer, lockup) in [
+            (
+                liftable_stake,
+                solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    unix_timestamp: 0,
+                    custodian,
+                },
+            ),
+            (
+                time_locked_stake,
+                solana_pubkey::new_rand(),
+                Lockup {
                     epoch: 1,
                     ..Lockup::default()
                 },
-                ..Meta::default()
-            };
-            let stake_account = Account::new_data_with_space(
-                balance,
-                &StakeStateV2::Initialized(meta),
-                StakeStateV2::size_of(),
-                &stake::program::id(),
-            )
-            .unwrap();
-            accounts.insert(pubkey, stake_account);
+            ),
+            // Also controlled by a hardcoded authority
+            (
+                authority_stake,
+                withdraw_authority()[0],
+                Lockup {
+                    epoch: 1,
+                    unix_timestamp: 0,
+                    custodian,
+                },
+            ),
+            (
+                expired_stake,
+                solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 0,
+                    unix_timestamp: 0,
+                    custodian,
+                },
+            ),
+        ] {
+            accounts.insert(pubkey, new_stake_account(10, &withdrawer, lockup));
         }
-
-        let slots_per_epoch = 32;
         let genesis_config = GenesisConfig {
             accounts,
-            epoch_schedule: EpochSchedule::new(slots_per_epoch),
-            cluster_type: ClusterType::MainnetBeta,
             ..GenesisConfig::default()
         };
-        let mut bank = Arc::new(Bank::new_for_tests(&genesis_config));
+        let bank = Bank::new_for_tests(&genesis_config);
+
         assert_eq!(
-            bank.capitalization(),
-            (num_genesis_accounts + num_non_circulating_accounts + num_stake_accounts) * balance
-                + genesis_sysvar_and_builtin_program_lamports(),
+            custodian_liftable_accounts(&bank).unwrap(),
+            vec![liftable_stake]
         );
+    }
 
-        let non_circulating_supply = calculate_non_circulating_supply(&bank).unwrap();
-        assert_eq!(
-            non_circulating_supply.lamports,
-            (num_non_circulating_accounts + num_stake_accounts) * balance
+    #[test]
+    fn test_calculate_non_circulating_supply_count_cooldown_stake() {
+        let new_deactivating_stake_account = |lamports: u64, stake_lamports: u64| {
+            new_delegated_stake_account(
+                lamports,
+                &solana_pubkey::new_rand(),
+                Lockup::default(),
+                Delegation {
+                    voter_pubkey: solana_pubkey::new_rand(),
+                    stake: stake_lamports,
+                    // Bootstrap stake, fully effective until its deactivation
+                    activation_epoch: u64::MAX,
+                    deactivation_epoch: 0,
+                    ..Delegation::default()
+                },
+            )
+        };
+        let cooling_down_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(cooling_down_stake, new_deactivating_stake_account(50, 40));
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(30, &solana_pubkey::new_rand(), Lockup::default()),
         );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        assert!(!supply.reasons.contains_key(&cooling_down_stake));
+        assert_eq!(supply.cooldown_lamports, 0);
+
+        let config = NonCirculatingSupplyConfig {
+            count_cooldown_stake: true,
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let cooldown_supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
         assert_eq!(
-            non_circulating_supply.accounts.len(),
-            num_non_circulating_accounts as usize + num_stake_accounts as usize
+            cooldown_supply.reasons[&cooling_down_stake],
+            NonCirculatingReason::Cooldown
         );
+        assert_eq!(cooldown_supply.balances[&cooling_down_stake], 40);
+        assert_eq!(cooldown_supply.cooldown_lamports, 40);
+        assert_eq!(cooldown_supply.lamports, supply.lamports + 40);
+    }
 
-        bank = Arc::new(new_from_parent(bank));
-        let new_balance = 11;
-        for key in non_circulating_accounts {
-            bank.store_account(
-                &key,
-                &AccountSharedData::new(new_balance, 0, &Pubkey::default()),
+    #[test]
+    fn test_hardcoded_accounts_counted_without_stake_accounts() {
+        let mut accounts = BTreeMap::new();
+        for (index, pubkey) in non_circulating_accounts().into_iter().enumerate() {
+            accounts.insert(
+                pubkey,
+                Account::new(1_000 + index as u64, 0, &Pubkey::default()),
             );
         }
-        let non_circulating_supply = calculate_non_circulating_supply(&bank).unwrap();
-        assert_eq!(
-            non_circulating_supply.lamports,
-            (num_non_circulating_accounts * new_balance) + (num_stake_accounts * balance)
-        );
-        assert_eq!(
-            non_circulating_supply.accounts.len(),
-            num_non_circulating_accounts as usize + num_stake_accounts as usize
-        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert!(load_stake_accounts(&bank).unwrap().is_empty());
 
-        // Advance bank an epoch, which should unlock stakes
-        for _ in 0..slots_per_epoch {
-            bank = Arc::new(new_from_parent(bank));
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+        let mut expected_lamports = 0;
+        for pubkey in non_circulating_accounts() {
+            let balance = bank.get_balance(&pubkey);
+            assert!(balance >= 1_000);
+            assert_eq!(supply.balances[&pubkey], balance);
+            assert_eq!(supply.reasons[&pubkey], NonCirculatingReason::Hardcoded);
+            assert!(supply.accounts.contains(&pubkey));
+            expected_lamports += balance;
         }
-        assert_eq!(bank.epoch(), 1);
-        let non_circulating_supply = calculate_non_circulating_supply(&bank).unwrap();
-        assert_eq!(
-            non_circulating_supply.lamports,
-            num_non_circulating_accounts * new_balance
-        );
-        assert_eq!(
-            non_circulating_supply.accounts.len(),
-            num_non_circulating_accounts as usize
-        );
+        assert_eq!(supply.lamports, expected_lamports);
+        assert_eq!(supply.accounts.len(), non_circulating_accounts().len());
     }
 }
@@ -0,0 +1,384 @@
+use {
+    super::{
+        is_non_circulating_stake_account, load_stake_accounts, non_circulating_accounts,
+        withdraw_authority,
+    },
+    crate::bank::Bank,
+    solana_account::{AccountSharedData, ReadableAccount},
+    solana_accounts_db::accounts_index::ScanResult,
+    solana_clock::{Clock, Epoch, Slot, UnixTimestamp},
+    solana_pubkey::Pubkey,
+    solana_stake_interface as stake,
+    solana_stake_program::stake_state,
+    std::{
+        collections::HashMap,
+        sync::{Mutex, RwLock},
+    },
+};
+
+/// Running total of the non-circulating lamports of the root bank, kept current by feeding it the
+/// writes to stake and hardcoded non-circulating accounts of every slot as it is rooted, so that
+/// supply queries don't need to scan the accounts index. A bank given the aggregate with
+/// `Bank::set_non_circulating_supply_aggregate()` feeds it the slots rooted by squashing the bank
+/// or its descendants. As writes are only fed once rooted, those of other forks never reach the
+/// aggregate, and those of unrooted banks aren't reflected until they are rooted.
+///
+/// Lockups expire without any account being written, so the aggregate is only available while
+/// the clock stays within the epoch it was built for and before the earliest unix timestamp
+/// lockup it counts expires. Past that, [`Self::lamports_or_rescan`] falls back to a scan.
+pub struct NonCirculatingSupplyAggregate {
+    hardcoded_accounts: Vec<Pubkey>,
+    withdraw_authority_list: Vec<Pubkey>,
+    state: RwLock<AggregateState>,
+    /// Held for the duration of a rescan, so that only one at a time collects `pending_writes`
+    rescan_lock: Mutex<()>,
+}
+
+#[derive(Default)]
+struct AggregateState {
+    epoch: Epoch,
+    /// Newest slot whose writes are reflected, so slots rooted again by squashing a descendant
+    /// aren't fed twice
+    slot: Slot,
+    /// Earliest unix timestamp at which a counted lockup stops being in force
+    expires_at: Option<UnixTimestamp>,
+    contributions: HashMap<Pubkey, u64>,
+    lamports: u64,
+    /// Writes made while a rescan is under way, replayed onto its result as the scan may have
+    /// read the accounts before or after they were written
+    pending_writes: Option<Vec<(Pubkey, AccountSharedData, Clock)>>,
+}
+
+impl NonCirculatingSupplyAggregate {
+    /// Seeds the aggregate from a single scan of `bank`, which should be the root bank
+    pub fn new(bank: &Bank) -> ScanResult<Self> {
+        let aggregate = Self {
+            hardcoded_accounts: non_circulating_accounts(),
+            withdraw_authority_list: withdraw_authority(),
+            state: RwLock::default(),
+            rescan_lock: Mutex::default(),
+        };
+        aggregate.rescan(bank)?;
+        Ok(aggregate)
+    }
+
+    /// Returns the aggregated non-circulating lamports, or `None` if lockups may have expired
+    /// since the aggregate was built
+    pub fn lamports(&self, bank: &Bank) -> Option<u64> {
+        let clock = bank.clock();
+        let state = self.state.read().unwrap();
+        let expired = state
+            .expires_at
+            .is_some_and(|expires_at| clock.unix_timestamp >= expires_at);
+        (state.epoch == clock.epoch && !expired).then_some(state.lamports)
+    }
+
+    /// Returns the aggregated non-circulating lamports, rescanning `bank` to rebuild the
+    /// aggregate if it is unavailable. As the rescan replaces the aggregate, `bank` should be the
+    /// root bank.
+    pub fn lamports_or_rescan(&self, bank: &Bank) -> ScanResult<u64> {
+        match self.lamports(bank) {
+            Some(lamports) => Ok(lamports),
+            None => self.rescan(bank),
+        }
+    }
+
+    /// Updates the aggregate with the writes of `rooted_slots`, newest first, which have just
+    /// been rooted by squashing `bank`. Writes to accounts that are neither hardcoded, nor owned
+    /// by the stake program, nor previously counted are ignored.
+    pub fn on_slots_rooted(&self, bank: &Bank, rooted_slots: &[Slot]) {
+        let clock = bank.clock();
+        let last_slot = self.state.read().unwrap().slot;
+        for slot in rooted_slots.iter().rev().filter(|slot| **slot > last_slot) {
+            let writes = bank.rc.accounts.scan_slot(*slot, |stored_account| {
+                self.is_tracked(stored_account.pubkey(), stored_account.owner())
+                    .then(|| (*stored_account.pubkey(), stored_account.take_account()))
+            });
+            for (pubkey, account) in writes {
+                self.on_account_write(&pubkey, account, &clock);
+            }
+        }
+        if let Some(newest_slot) = rooted_slots.iter().max() {
+            let mut state = self.state.write().unwrap();
+            state.slot = state.slot.max(*newest_slot);
+        }
+    }
+
+    fn is_tracked(&self, pubkey: &Pubkey, owner: &Pubkey) -> bool {
+        self.hardcoded_accounts.contains(pubkey)
+            || owner == &stake::program::id()
+            || self
+                .state
+                .read()
+                .unwrap()
+                .contributions
+                .contains_key(pubkey)
+    }
+
+    fn on_account_write(&self, pubkey: &Pubkey, account: AccountSharedData, clock: &Clock) {
+        let mut state = self.state.write().unwrap();
+        self.apply_write(&mut state, pubkey, &account, clock);
+        if let Some(pending_writes) = state.pending_writes.as_mut() {
+            pending_writes.push((*pubkey, account, clock.clone()));
+        }
+    }
+
+    fn apply_write(
+        &self,
+        state: &mut AggregateState,
+        pubkey: &Pubkey,
+        account: &AccountSharedData,
+        clock: &Clock,
+    ) {
+        let contribution = if self.hardcoded_accounts.contains(pubkey) {
+            Some(account.lamports())
+        } else if account.owner() == &stake::program::id() {
+            self.classify_stake_account(account, clock, state)
+        } else {
+            // Closed, or reassigned away from the stake program
+            None
+        };
+        state.set_contribution(pubkey, contribution);
+    }
+
+    fn rescan(&self, bank: &Bank) -> ScanResult<u64> {
+        self.rescan_with(bank, || load_stake_accounts(bank))
+    }
+
+    fn rescan_with(
+        &self,
+        bank: &Bank,
+        load_stake_accounts: impl FnOnce() -> ScanResult<Vec<(Pubkey, AccountSharedData)>>,
+    ) -> ScanResult<u64> {
+        let _rescan_lock = self.rescan_lock.lock().unwrap();
+        self.state.write().unwrap().pending_writes = Some(vec![]);
+        let clock = bank.clock();
+        let stake_accounts = match load_stake_accounts() {
+            Ok(stake_accounts) => stake_accounts,
+            Err(err) => {
+                self.state.write().unwrap().pending_writes = None;
+                return Err(err);
+            }
+        };
+        let mut rescanned = AggregateState {
+            epoch: clock.epoch,
+            slot: bank.slot(),
+            ..AggregateState::default()
+        };
+        for pubkey in &self.hardcoded_accounts {
+            rescanned.set_contribution(pubkey, Some(bank.get_balance(pubkey)));
+        }
+        for (pubkey, account) in stake_accounts.iter() {
+            let contribution = self.classify_stake_account(account, &clock, &mut rescanned);
+            rescanned.set_contribution(pubkey, contribution);
+        }
+        let mut state = self.state.write().unwrap();
+        for (pubkey, account, clock) in state.pending_writes.take().unwrap_or_default() {
+            self.apply_write(&mut rescanned, &pubkey, &account, &clock);
+        }
+        let lamports = rescanned.lamports;
+        rescanned.slot = rescanned.slot.max(state.slot);
+        *state = rescanned;
+        Ok(lamports)
+    }
+
+    fn classify_stake_account(
+        &self,
+        account: &AccountSharedData,
+        clock: &Clock,
+        state: &mut AggregateState,
+    ) -> Option<u64> {
+        if !is_non_circulating_stake_account(account, clock, &self.withdraw_authority_list) {
+            return None;
+        }
+        if let Some(lockup) = stake_state::lockup_from(account) {
+            if lockup.unix_timestamp > clock.unix_timestamp {
+                state.expires_at = Some(
+                    state
+                        .expires_at
+                        .map_or(lockup.unix_timestamp, |expires_at| {
+                            expires_at.min(lockup.unix_timestamp)
+                        }),
+                );
+            }
+        }
+        Some(account.lamports())
+    }
+}
+
+impl AggregateState {
+    fn set_contribution(&mut self, pubkey: &Pubkey, contribution: Option<u64>) {
+        let previous = match contribution {
+            Some(lamports) => self.contributions.insert(*pubkey, lamports),
+            None => self.contributions.remove(pubkey),
+        };
+        self.lamports =
+            self.lamports - previous.unwrap_or_default() + contribution.unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{super::tests::new_stake_account, *},
+        crate::non_circulating_supply::calculate_non_circulating_supply,
+        solana_epoch_schedule::EpochSchedule,
+        solana_genesis_config::GenesisConfig,
+        solana_stake_interface::state::Lockup,
+        std::{collections::BTreeMap, sync::Arc},
+    };
+
+    #[test]
+    fn test_aggregate_updates_on_stake_account_write() {
+        let locked_stake = solana_pubkey::new_rand();
+        let lockup = Lockup {
+            epoch: 10,
+            ..Lockup::default()
+        };
+        let withdrawer = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(locked_stake, new_stake_account(10, &withdrawer, lockup));
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(20, &withdrawer, Lockup::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            epoch_schedule: EpochSchedule::without_warmup(),
+            ..GenesisConfig::default()
+        };
+        let mut bank0 = Bank::new_for_tests(&genesis_config);
+        let aggregate = Arc::new(NonCirculatingSupplyAggregate::new(&bank0).unwrap());
+        bank0.set_non_circulating_supply_aggregate(aggregate.clone());
+        let bank0 = Arc::new(bank0);
+        assert_eq!(aggregate.lamports(&bank0), Some(10));
+
+        // Stores by descendants are fed to the aggregate once rooted, after which it reports the
+        // new total without rescanning
+        let bank1 = Arc::new(Bank::new_from_parent(bank0, &Pubkey::default(), 1));
+        let account: AccountSharedData = new_stake_account(30, &withdrawer, lockup).into();
+        bank1.store_account(&locked_stake, &account);
+        assert_eq!(aggregate.lamports(&bank1), Some(10));
+        bank1.squash();
+        assert_eq!(aggregate.lamports(&bank1), Some(30));
+        assert_eq!(
+            aggregate.lamports(&bank1).unwrap(),
+            calculate_non_circulating_supply(&bank1).unwrap().lamports
+        );
+
+        let bank2 = Arc::new(Bank::new_from_parent(bank1, &Pubkey::default(), 2));
+        let unlocked: AccountSharedData =
+            new_stake_account(30, &withdrawer, Lockup::default()).into();
+        bank2.store_account(&locked_stake, &unlocked);
+        bank2.squash();
+        assert_eq!(aggregate.lamports(&bank2), Some(0));
+
+        // Squashing a descendant roots `bank2` again, without feeding its writes twice
+        let bank3 = Bank::new_from_parent(bank2, &Pubkey::default(), 3);
+        bank3.squash();
+        assert_eq!(aggregate.lamports(&bank3), Some(0));
+    }
+
+    #[test]
+    fn test_aggregate_sibling_banks() {
+        let locked_stake = solana_pubkey::new_rand();
+        let lockup = Lockup {
+            epoch: 10,
+            ..Lockup::default()
+        };
+        let withdrawer = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(locked_stake, new_stake_account(10, &withdrawer, lockup));
+        let genesis_config = GenesisConfig {
+            accounts,
+            epoch_schedule: EpochSchedule::without_warmup(),
+            ..GenesisConfig::default()
+        };
+        let mut bank0 = Bank::new_for_tests(&genesis_config);
+        let aggregate = Arc::new(NonCirculatingSupplyAggregate::new(&bank0).unwrap());
+        bank0.set_non_circulating_supply_aggregate(aggregate.clone());
+        let bank0 = Arc::new(bank0);
+
+        // Two forks write the same stake account; neither shows until one of them is rooted
+        let bank1 = Bank::new_from_parent(bank0.clone(), &Pubkey::default(), 1);
+        let bank2 = Bank::new_from_parent(bank0, &Pubkey::default(), 2);
+        let account: AccountSharedData = new_stake_account(30, &withdrawer, lockup).into();
+        bank1.store_account(&locked_stake, &account);
+        let account: AccountSharedData = new_stake_account(50, &withdrawer, lockup).into();
+        bank2.store_account(&locked_stake, &account);
+        assert_eq!(aggregate.lamports(&bank1), Some(10));
+        assert_eq!(aggregate.lamports(&bank2), Some(10));
+
+        // Only the rooted fork's write is counted
+        bank2.squash();
+        assert_eq!(aggregate.lamports(&bank2), Some(50));
+        assert_eq!(
+            aggregate.lamports(&bank2).unwrap(),
+            calculate_non_circulating_supply(&bank2).unwrap().lamports
+        );
+    }
+
+    #[test]
+    fn test_aggregate_write_during_rescan() {
+        let locked_stake = solana_pubkey::new_rand();
+        let lockup = Lockup {
+            epoch: 10,
+            ..Lockup::default()
+        };
+        let withdrawer = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(locked_stake, new_stake_account(10, &withdrawer, lockup));
+        let genesis_config = GenesisConfig {
+            accounts,
+            epoch_schedule: EpochSchedule::without_warmup(),
+            ..GenesisConfig::default()
+        };
+        let mut bank0 = Bank::new_for_tests(&genesis_config);
+        let aggregate = Arc::new(NonCirculatingSupplyAggregate::new(&bank0).unwrap());
+        bank0.set_non_circulating_supply_aggregate(aggregate.clone());
+        let bank0 = Arc::new(bank0);
+        let bank1 = Bank::new_from_parent(bank0.clone(), &Pubkey::default(), 1);
+
+        // The account is written after the rescan has read it
+        let account: AccountSharedData = new_stake_account(30, &withdrawer, lockup).into();
+        let lamports = aggregate
+            .rescan_with(&bank0, || {
+                let stake_accounts = load_stake_accounts(&bank0);
+                bank1.store_account(&locked_stake, &account);
+                bank1.squash();
+                stake_accounts
+            })
+            .unwrap();
+        assert_eq!(lamports, 30);
+        assert_eq!(aggregate.lamports(&bank1), Some(30));
+    }
+
+    #[test]
+    fn test_aggregate_falls_back_to_rescan() {
+        let lockup = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(10, &solana_pubkey::new_rand(), lockup),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            epoch_schedule: EpochSchedule::without_warmup(),
+            ..GenesisConfig::default()
+        };
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+        let aggregate = NonCirculatingSupplyAggregate::new(&bank0).unwrap();
+        assert_eq!(aggregate.lamports(&bank0), Some(10));
+
+        // Crossing into epoch 1 unlocks the stake without any write
+        let slot = bank0.epoch_schedule().get_first_slot_in_epoch(1);
+        let bank1 = Bank::new_from_parent(bank0, &Pubkey::default(), slot);
+        assert_eq!(bank1.epoch(), 1);
+        assert_eq!(aggregate.lamports(&bank1), None);
+        assert_eq!(aggregate.lamports_or_rescan(&bank1).unwrap(), 0);
+        assert_eq!(aggregate.lamports(&bank1), Some(0));
+    }
+}
@@ -0,0 +1,270 @@
+use {
+    super::{calculate_non_circulating_supply, NonCirculatingSupply, StaticLists},
+    crate::bank::Bank,
+    log::*,
+    solana_accounts_db::accounts_index::ScanResult,
+    solana_clock::{Epoch, Slot},
+    solana_pubkey::Pubkey,
+    std::{
+        sync::{Arc, RwLock},
+        thread::{Builder, JoinHandle},
+    },
+};
+
+/// Caches the non-circulating supply of the most recently queried epoch, so repeated supply
+/// queries don't each pay for a scan of the stake program accounts
+#[derive(Default)]
+pub struct NonCirculatingSupplyCache {
+    entry: RwLock<Option<CacheEntry>>,
+    /// Number of slots after which a cached supply is recalculated even within its epoch, as
+    /// balances keep changing between epoch boundaries
+    ttl_slots: Option<Slot>,
+    /// Accounts registered at runtime as hardcoded non-circulating, on top of
+    /// `non_circulating_accounts()`
+    additional_hardcoded_accounts: RwLock<Vec<Pubkey>>,
+}
+
+struct CacheEntry {
+    epoch: Epoch,
+    /// Slot of the bank the supply was calculated for
+    slot: Slot,
+    supply: Arc<NonCirculatingSupply>,
+}
+
+impl NonCirculatingSupplyCache {
+    /// Creates a cache whose supply expires `ttl_slots` slots after the slot it was calculated
+    /// for, even within the same epoch
+    pub fn with_ttl_slots(ttl_slots: Slot) -> Self {
+        Self {
+            ttl_slots: Some(ttl_slots),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the supply cached for `bank`'s epoch, calculating and caching it on a miss or
+    /// once the cached supply has outlived the TTL
+    pub fn get_or_calculate(&self, bank: &Bank) -> ScanResult<Arc<NonCirculatingSupply>> {
+        if let Some(entry) = self.entry.read().unwrap().as_ref() {
+            if entry.epoch == bank.epoch() && !self.is_expired(entry, bank.slot()) {
+                return Ok(entry.supply.clone());
+            }
+        }
+        self.calculate_and_store(bank)
+            .map(|(_previous, current)| current)
+    }
+
+    /// Returns the previously cached supply, if any, along with a freshly calculated one which
+    /// replaces it in the cache, so callers can diff the two without tracking state themselves
+    pub fn last_and_current(
+        &self,
+        bank: &Bank,
+    ) -> ScanResult<(Option<Arc<NonCirculatingSupply>>, Arc<NonCirculatingSupply>)> {
+        self.calculate_and_store(bank)
+    }
+
+    /// Registers `pubkey` as a hardcoded non-circulating account. As hardcoded accounts need no
+    /// scan, a supply cached for `bank`'s epoch is patched in place with the account's balance
+    /// rather than invalidated.
+    pub fn add_hardcoded_account(&self, bank: &Bank, pubkey: Pubkey) {
+        let mut additional_hardcoded_accounts = self.additional_hardcoded_accounts.write().unwrap();
+        if additional_hardcoded_accounts.contains(&pubkey) {
+            return;
+        }
+        additional_hardcoded_accounts.push(pubkey);
+        if let Some(entry) = self.entry.write().unwrap().as_mut() {
+            if entry.epoch == bank.epoch() {
+                Arc::make_mut(&mut entry.supply).include_hardcoded_account(
+                    &StaticLists::new(),
+                    pubkey,
+                    bank.get_balance(&pubkey),
+                );
+            }
+        }
+    }
+
+    /// Calculates and caches the supply of `bank` on a background thread, e.g. right after an
+    /// epoch boundary, so the first query for the new epoch is served from the cache. The thread
+    /// keeps neither the cache nor the bank alive; nothing is calculated if either is dropped
+    /// before it gets to run, and a calculation finishing after the cache is dropped is discarded.
+    pub fn spawn_supply_prewarm(self: &Arc<Self>, bank: Arc<Bank>) -> JoinHandle<()> {
+        let cache = Arc::downgrade(self);
+        let weak_bank = Arc::downgrade(&bank);
+        drop(bank);
+        Builder::new()
+            .name("solSupplyPrewrm".to_string())
+            .spawn(move || {
+                let Some(bank) = weak_bank.upgrade() else {
+                    return;
+                };
+                let Some(cache) = cache.upgrade() else {
+                    return;
+                };
+                if let Err(err) = cache.get_or_calculate(&bank) {
+                    warn!("Failed to prewarm the non-circulating supply cache: {err}");
+                }
+            })
+            .unwrap()
+    }
+
+    fn is_expired(&self, entry: &CacheEntry, slot: Slot) -> bool {
+        self.ttl_slots
+            .is_some_and(|ttl_slots| slot >= entry.slot.saturating_add(ttl_slots))
+    }
+
+    fn calculate_and_store(
+        &self,
+        bank: &Bank,
+    ) -> ScanResult<(Option<Arc<NonCirculatingSupply>>, Arc<NonCirculatingSupply>)> {
+        let mut supply = calculate_non_circulating_supply(bank)?;
+        let static_lists = StaticLists::new();
+        for pubkey in self.additional_hardcoded_accounts.read().unwrap().iter() {
+            supply.include_hardcoded_account(&static_lists, *pubkey, bank.get_balance(pubkey));
+        }
+        let supply = Arc::new(supply);
+        let previous = self.entry.write().unwrap().replace(CacheEntry {
+            epoch: bank.epoch(),
+            slot: bank.slot(),
+            supply: supply.clone(),
+        });
+        Ok((previous.map(|entry| entry.supply), supply))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{super::tests::new_stake_account, *},
+        crate::non_circulating_supply::NonCirculatingReason,
+        solana_account::Account,
+        solana_genesis_config::{create_genesis_config, GenesisConfig},
+        solana_stake_interface::state::Lockup,
+        std::collections::BTreeMap,
+    };
+
+    #[test]
+    fn test_last_and_current() {
+        let (genesis_config, _mint_keypair) = create_genesis_config(500);
+        let bank = Bank::new_for_tests(&genesis_config);
+        let cache = NonCirculatingSupplyCache::default();
+
+        let (previous, first) = cache.last_and_current(&bank).unwrap();
+        assert!(previous.is_none());
+
+        let (previous, second) = cache.last_and_current(&bank).unwrap();
+        assert!(Arc::ptr_eq(&previous.unwrap(), &first));
+        assert!(!Arc::ptr_eq(&second, &first));
+        assert!(Arc::ptr_eq(
+            &cache.get_or_calculate(&bank).unwrap(),
+            &second
+        ));
+    }
+
+    #[test]
+    fn test_add_hardcoded_account_patches_cache() {
+        let registered = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(registered, Account::new(50, 0, &Pubkey::default()));
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let cache = NonCirculatingSupplyCache::default();
+
+        let before = cache.get_or_calculate(&bank).unwrap();
+        let lamports_before = before.lamports;
+        assert!(!before.reasons.contains_key(&registered));
+        let cached_ptr = Arc::as_ptr(&before);
+        drop(before);
+
+        cache.add_hardcoded_account(&bank, registered);
+        let after = cache.get_or_calculate(&bank).unwrap();
+        // The cached allocation was patched rather than replaced by a recalculation
+        assert_eq!(Arc::as_ptr(&after), cached_ptr);
+        assert_eq!(after.lamports, lamports_before + 50);
+        assert_eq!(after.reasons[&registered], NonCirculatingReason::Hardcoded);
+        assert!(after.accounts.contains(&registered));
+
+        // A recalculation keeps the registered account
+        let (_previous, current) = cache.last_and_current(&bank).unwrap();
+        assert_eq!(current.lamports, after.lamports);
+        assert!(current.accounts.contains(&registered));
+    }
+
+    #[test]
+    fn test_get_or_calculate_ttl() {
+        let stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            stake,
+            new_stake_account(
+                100,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+        let cache = NonCirculatingSupplyCache::with_ttl_slots(4);
+        let cached = cache.get_or_calculate(&bank0).unwrap();
+        assert_eq!(cached.balances[&stake], 100);
+
+        let bank2 = Arc::new(Bank::new_from_parent(bank0, &Pubkey::default(), 2));
+        bank2.store_account(
+            &stake,
+            &new_stake_account(
+                300,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            )
+            .into(),
+        );
+        // Within the TTL, the stale supply is served
+        let stale = cache.get_or_calculate(&bank2).unwrap();
+        assert!(Arc::ptr_eq(&stale, &cached));
+        assert_eq!(stale.balances[&stake], 100);
+
+        // Past the TTL, the supply is recalculated
+        let bank5 = Bank::new_from_parent(bank2, &Pubkey::default(), 5);
+        assert_eq!(bank5.epoch(), 0);
+        let recalculated = cache.get_or_calculate(&bank5).unwrap();
+        assert!(!Arc::ptr_eq(&recalculated, &cached));
+        assert_eq!(recalculated.balances[&stake], 300);
+        assert!(Arc::ptr_eq(
+            &cache.get_or_calculate(&bank5).unwrap(),
+            &recalculated
+        ));
+    }
+
+    #[test]
+    fn test_spawn_supply_prewarm() {
+        let (genesis_config, _mint_keypair) = create_genesis_config(500);
+        let bank = Arc::new(Bank::new_for_tests(&genesis_config));
+        let cache = Arc::new(NonCirculatingSupplyCache::default());
+
+        cache.spawn_supply_prewarm(bank.clone()).join().unwrap();
+        let prewarmed = cache.entry.read().unwrap().as_ref().unwrap().supply.clone();
+        assert!(Arc::ptr_eq(
+            &cache.get_or_calculate(&bank).unwrap(),
+            &prewarmed
+        ));
+
+        // Nothing is calculated for a bank dropped before the thread runs
+        let cache = Arc::new(NonCirculatingSupplyCache::default());
+        let (genesis_config, _mint_keypair) = create_genesis_config(500);
+        let dropped_bank = Arc::new(Bank::new_for_tests(&genesis_config));
+        let weak_bank = Arc::downgrade(&dropped_bank);
+        cache.spawn_supply_prewarm(dropped_bank).join().unwrap();
+        assert!(weak_bank.upgrade().is_none());
+        assert!(cache.entry.read().unwrap().is_none());
+    }
+}
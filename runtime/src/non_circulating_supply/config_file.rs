@@ -0,0 +1,258 @@
+use {
+    super::{DecodeFailureMode, LamportsScale, NonCirculatingSupplyConfig},
+    solana_clock::Slot,
+    solana_pubkey::Pubkey,
+    std::{
+        fs::File,
+        io::{self, BufReader},
+        ops::Range,
+        path::Path,
+    },
+    thiserror::Error,
+};
+
+#[derive(Error, Debug)]
+pub enum ConfigFileError {
+    #[error("failed to read config file: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid config file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid pubkey {value:?} in `{field}`")]
+    InvalidPubkey { field: &'static str, value: String },
+}
+
+/// Operator configuration of the supply calculation, as read from a JSON file. Omitted fields
+/// keep their defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    additional_non_circulating_accounts: Vec<String>,
+    additional_withdraw_authorities: Vec<String>,
+    foundation_reserve: Vec<String>,
+    denied_accounts: Vec<String>,
+    excluded_accounts: Vec<String>,
+    hard_custodians: Option<Vec<String>>,
+    min_lamports: Option<u64>,
+    consolidate_dust_below: Option<u64>,
+    incinerator_as_burned: bool,
+    exclude_dust_hardcoded_from_list: bool,
+    exclude_rent_exempt_reserve: bool,
+    parallel: bool,
+    decode_failure_mode: DecodeFailureMode,
+    catch_decode_panics: bool,
+    report_activating_stake: bool,
+    verify_reason_partition: bool,
+    count_cooldown_stake: bool,
+    verify_double_scan: bool,
+    as_of_unix: Option<i64>,
+    protocol_fees_as_circulating: bool,
+    require_hardcoded_present: bool,
+    delegated_stake_only: bool,
+    scale: LamportsScale,
+    stake_account_slots: Option<Range<Slot>>,
+}
+
+fn parse_pubkeys(field: &'static str, values: Vec<String>) -> Result<Vec<Pubkey>, ConfigFileError> {
+    values
+        .into_iter()
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| ConfigFileError::InvalidPubkey { field, value })
+        })
+        .collect()
+}
+
+impl NonCirculatingSupplyConfig {
+    /// Loads the configuration from the JSON file at `path`, e.g.
+    ///
+    /// ```json
+    /// {
+    ///   "additional_non_circulating_accounts": ["<pubkey>"],
+    ///   "additional_withdraw_authorities": ["<pubkey>"],
+    ///   "hard_custodians": ["<pubkey>"],
+    ///   "min_lamports": 1000000000,
+    ///   "incinerator_as_burned": true,
+    ///   "decode_failure_mode": "strict",
+    ///   "scale": { "numerator": 1, "denominator": 1000 },
+    ///   "stake_account_slots": { "start": 0, "end": 1000 }
+    /// }
+    /// ```
+    ///
+    /// Settings that can't be expressed in JSON, `classifiers`, `exclude_stake_accounts`,
+    /// `include_stake_accounts` and `pinned_view`, keep their defaults, as do the what-if
+    /// overrides of the bank's sysvars, `clock`, `rent` and `epoch_schedule`.
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self, ConfigFileError> {
+        let file = File::open(path)?;
+        let config_file: ConfigFile = serde_json::from_reader(BufReader::new(file))?;
+        Ok(Self {
+            additional_non_circulating_accounts: parse_pubkeys(
+                "additional_non_circulating_accounts",
+                config_file.additional_non_circulating_accounts,
+            )?,
+            additional_withdraw_authorities: parse_pubkeys(
+                "additional_withdraw_authorities",
+                config_file.additional_withdraw_authorities,
+            )?,
+            foundation_reserve: parse_pubkeys(
+                "foundation_reserve",
+                config_file.foundation_reserve,
+            )?,
+            denied_accounts: parse_pubkeys("denied_accounts", config_file.denied_accounts)?,
+            excluded_accounts: parse_pubkeys("excluded_accounts", config_file.excluded_accounts)?,
+            hard_custodians: config_file
+                .hard_custodians
+                .map(|hard_custodians| parse_pubkeys("hard_custodians", hard_custodians))
+                .transpose()?,
+            min_lamports: config_file.min_lamports,
+            consolidate_dust_below: config_file.consolidate_dust_below,
+            incinerator_as_burned: config_file.incinerator_as_burned,
+            exclude_dust_hardcoded_from_list: config_file.exclude_dust_hardcoded_from_list,
+            exclude_rent_exempt_reserve: config_file.exclude_rent_exempt_reserve,
+            parallel: config_file.parallel,
+            decode_failure_mode: config_file.decode_failure_mode,
+            catch_decode_panics: config_file.catch_decode_panics,
+            report_activating_stake: config_file.report_activating_stake,
+            verify_reason_partition: config_file.verify_reason_partition,
+            count_cooldown_stake: config_file.count_cooldown_stake,
+            verify_double_scan: config_file.verify_double_scan,
+            as_of_unix: config_file.as_of_unix,
+            protocol_fees_as_circulating: config_file.protocol_fees_as_circulating,
+            require_hardcoded_present: config_file.require_hardcoded_present,
+            delegated_stake_only: config_file.delegated_stake_only,
+            scale: config_file.scale,
+            stake_account_slots: config_file.stake_account_slots,
+            ..Self::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{super::tests::new_stake_account, *},
+        crate::{
+            bank::Bank,
+            non_circulating_supply::{
+                calculate_non_circulating_supply_with_config, NonCirculatingReason,
+            },
+        },
+        solana_account::Account,
+        solana_genesis_config::GenesisConfig,
+        solana_stake_interface::state::Lockup,
+        std::{collections::BTreeMap, io::Write},
+    };
+
+    fn write_config(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_from_json_file() {
+        let treasury = solana_pubkey::new_rand();
+        let authority = solana_pubkey::new_rand();
+        let authority_stake = solana_pubkey::new_rand();
+        let small_stake = solana_pubkey::new_rand();
+        let denied_stake = solana_pubkey::new_rand();
+        let excluded = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(treasury, Account::new(1_000, 0, &Pubkey::default()));
+        accounts.insert(excluded, Account::new(1_000, 0, &Pubkey::default()));
+        accounts.insert(
+            denied_stake,
+            new_stake_account(500, &authority, Lockup::default()),
+        );
+        accounts.insert(
+            authority_stake,
+            new_stake_account(500, &authority, Lockup::default()),
+        );
+        accounts.insert(
+            small_stake,
+            new_stake_account(
+                5,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let file = write_config(&format!(
+            r#"{{
+                "additional_non_circulating_accounts": ["{treasury}", "{excluded}"],
+                "additional_withdraw_authorities": ["{authority}"],
+                "denied_accounts": ["{denied_stake}"],
+                "excluded_accounts": ["{excluded}"],
+                "hard_custodians": [],
+                "min_lamports": 10,
+                "consolidate_dust_below": 1,
+                "incinerator_as_burned": true,
+                "exclude_dust_hardcoded_from_list": false,
+                "exclude_rent_exempt_reserve": false,
+                "parallel": true,
+                "decode_failure_mode": "strict",
+                "catch_decode_panics": true,
+                "report_activating_stake": true,
+                "verify_reason_partition": true,
+                "scale": {{ "numerator": 1, "denominator": 2 }},
+                "stake_account_slots": {{ "start": 0, "end": 10 }}
+            }}"#
+        ));
+        let config = NonCirculatingSupplyConfig::from_json_file(file.path()).unwrap();
+        assert_eq!(
+            config.additional_non_circulating_accounts,
+            vec![treasury, excluded]
+        );
+        assert_eq!(config.denied_accounts, vec![denied_stake]);
+        assert_eq!(config.excluded_accounts, vec![excluded]);
+        assert_eq!(config.decode_failure_mode, DecodeFailureMode::Strict);
+        assert_eq!(config.hard_custodians, Some(vec![]));
+        assert_eq!(
+            config.scale,
+            LamportsScale {
+                numerator: 1,
+                denominator: 2,
+            }
+        );
+        assert_eq!(config.stake_account_slots, Some(0..10));
+
+        let supply = calculate_non_circulating_supply_with_config(&bank, &config).unwrap();
+        assert_eq!(supply.reasons[&treasury], NonCirculatingReason::Hardcoded);
+        assert_eq!(
+            supply.reasons[&authority_stake],
+            NonCirculatingReason::WithdrawAuthority
+        );
+        assert!(!supply.reasons.contains_key(&small_stake));
+        assert!(!supply.reasons.contains_key(&denied_stake));
+        // The explicit exclusion wins over the allowlist
+        assert!(!supply.reasons.contains_key(&excluded));
+    }
+
+    #[test]
+    fn test_from_json_file_errors() {
+        let file = write_config(r#"{"hard_custodians": ["not a pubkey"]}"#);
+        assert!(matches!(
+            NonCirculatingSupplyConfig::from_json_file(file.path()),
+            Err(ConfigFileError::InvalidPubkey {
+                field: "hard_custodians",
+                ..
+            })
+        ));
+
+        let file = write_config(r#"{"min_lamport": 10}"#);
+        let err = NonCirculatingSupplyConfig::from_json_file(file.path()).unwrap_err();
+        assert!(err.to_string().contains("min_lamport"), "{err}");
+
+        let file = write_config(r#"{"decode_failure_mode": "lax"}"#);
+        let err = NonCirculatingSupplyConfig::from_json_file(file.path()).unwrap_err();
+        assert!(err.to_string().contains("lax"), "{err}");
+    }
+}
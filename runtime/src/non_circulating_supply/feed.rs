@@ -0,0 +1,200 @@
+use {
+    super::{
+        non_circulating_accounts, stake_account_reason, withdraw_authority, NonCirculatingReason,
+        NonCirculatingSupply, StaticLists,
+    },
+    solana_account::{AccountSharedData, ReadableAccount},
+    solana_clock::{Clock, Slot},
+    solana_pubkey::Pubkey,
+    solana_stake_interface as stake,
+    std::collections::HashMap,
+};
+
+/// Maintains the non-circulating supply from a streamed account feed, e.g. a Geyser gRPC
+/// subscription, for consumers that don't run a bank. Only the latest write to each hardcoded or
+/// stake account is retained; classification happens against the clock source when the supply is
+/// queried, since lockups expire without any account being written.
+pub struct GrpcSupplyBuilder {
+    clock: Box<dyn Fn() -> Clock + Send + Sync>,
+    hardcoded_accounts: Vec<Pubkey>,
+    withdraw_authority_list: Vec<Pubkey>,
+    static_lists: StaticLists,
+    accounts: HashMap<Pubkey, (AccountSharedData, Slot)>,
+}
+
+impl GrpcSupplyBuilder {
+    pub fn new(clock: impl Fn() -> Clock + Send + Sync + 'static) -> Self {
+        Self {
+            clock: Box::new(clock),
+            hardcoded_accounts: non_circulating_accounts(),
+            withdraw_authority_list: withdraw_authority(),
+            static_lists: StaticLists::new(),
+            accounts: HashMap::new(),
+        }
+    }
+
+    /// Applies a write of `account` to `pubkey` at `slot`. Writes older than the latest one seen
+    /// for the account are ignored, as streams may deliver updates out of order.
+    pub fn ingest(&mut self, pubkey: Pubkey, account: AccountSharedData, slot: Slot) {
+        if self
+            .accounts
+            .get(&pubkey)
+            .is_some_and(|(_account, latest_slot)| *latest_slot > slot)
+        {
+            return;
+        }
+        let is_tracked = self.hardcoded_accounts.contains(&pubkey)
+            || (account.owner() == &stake::program::id() && account.lamports() > 0);
+        if is_tracked {
+            self.accounts.insert(pubkey, (account, slot));
+        } else {
+            self.accounts.remove(&pubkey);
+        }
+    }
+
+    /// Returns the non-circulating supply of the accounts ingested so far
+    pub fn supply(&self) -> NonCirculatingSupply {
+        let clock = (self.clock)();
+        let mut supply = NonCirculatingSupply {
+            lamports: 0,
+            accounts: vec![],
+            reasons: HashMap::new(),
+            balances: HashMap::new(),
+            burned_lamports: 0,
+            dust: None,
+            undecodable_accounts: vec![],
+            misowned_accounts: vec![],
+            panicked_decodes: 0,
+            activating_lamports: 0,
+            cooldown_lamports: 0,
+            reserve_lamports: 0,
+            static_lamports: 0,
+            dynamic_lamports: 0,
+            stale_authorities: vec![],
+            locked_rewards_lamports: 0,
+            lockups: HashMap::new(),
+            epoch: clock.epoch,
+        };
+        for pubkey in self.hardcoded_accounts.iter() {
+            let balance = self
+                .accounts
+                .get(pubkey)
+                .map_or(0, |(account, _slot)| account.lamports());
+            supply.include_hardcoded_account(&self.static_lists, *pubkey, balance);
+        }
+        for (pubkey, (account, _slot)) in self.accounts.iter() {
+            if self.hardcoded_accounts.contains(pubkey) {
+                continue;
+            }
+            if let Some(reason) =
+                stake_account_reason(account, &clock, &self.withdraw_authority_list)
+            {
+                supply.include_stake_account(
+                    &self.static_lists,
+                    *pubkey,
+                    reason,
+                    account,
+                    account.lamports(),
+                );
+            }
+        }
+        supply
+    }
+
+    /// Returns the reason `pubkey` is currently non-circulating, if it is
+    pub fn reason(&self, pubkey: &Pubkey) -> Option<NonCirculatingReason> {
+        if self.hardcoded_accounts.contains(pubkey) {
+            return Some(NonCirculatingReason::Hardcoded);
+        }
+        let (account, _slot) = self.accounts.get(pubkey)?;
+        stake_account_reason(account, &(self.clock)(), &self.withdraw_authority_list)
+    }
+}
+
+impl Extend<(Pubkey, AccountSharedData, Slot)> for GrpcSupplyBuilder {
+    fn extend<T: IntoIterator<Item = (Pubkey, AccountSharedData, Slot)>>(&mut self, iter: T) {
+        for (pubkey, account, slot) in iter {
+            self.ingest(pubkey, account, slot);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{super::tests::new_stake_account, *},
+        crate::{bank::Bank, non_circulating_supply::calculate_non_circulating_supply},
+        solana_genesis_config::GenesisConfig,
+        solana_stake_interface::state::Lockup,
+        std::collections::BTreeMap,
+    };
+
+    #[test]
+    fn test_grpc_supply_builder_matches_bank() {
+        let lockup = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let withdrawer = solana_pubkey::new_rand();
+        let locked_stake = solana_pubkey::new_rand();
+        let unlocked_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(locked_stake, new_stake_account(10, &withdrawer, lockup));
+        accounts.insert(
+            unlocked_stake,
+            new_stake_account(20, &withdrawer, Lockup::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        let clock = bank.clock();
+        let mut builder = GrpcSupplyBuilder::new(move || clock.clone());
+        builder.extend([
+            (
+                locked_stake,
+                AccountSharedData::from(new_stake_account(5, &withdrawer, lockup)),
+                0,
+            ),
+            (
+                unlocked_stake,
+                AccountSharedData::from(new_stake_account(20, &withdrawer, lockup)),
+                1,
+            ),
+            (
+                locked_stake,
+                AccountSharedData::from(new_stake_account(10, &withdrawer, lockup)),
+                2,
+            ),
+            (
+                unlocked_stake,
+                AccountSharedData::from(new_stake_account(20, &withdrawer, Lockup::default())),
+                3,
+            ),
+            // Stale update delivered out of order
+            (
+                locked_stake,
+                AccountSharedData::from(new_stake_account(7, &withdrawer, lockup)),
+                1,
+            ),
+        ]);
+        for pubkey in non_circulating_accounts() {
+            if let Some(account) = bank.get_account(&pubkey) {
+                builder.ingest(pubkey, account, 0);
+            }
+        }
+
+        let streamed = builder.supply();
+        let expected = calculate_non_circulating_supply(&bank).unwrap();
+        assert_eq!(streamed.lamports, expected.lamports);
+        assert_eq!(streamed.reasons, expected.reasons);
+        assert_eq!(streamed.balances, expected.balances);
+        assert_eq!(
+            builder.reason(&locked_stake),
+            Some(NonCirculatingReason::Lockup)
+        );
+        assert_eq!(builder.reason(&unlocked_stake), None);
+    }
+}
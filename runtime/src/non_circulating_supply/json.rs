@@ -0,0 +1,170 @@
+use {
+    super::{
+        non_circulating_accounts, scan_program_accounts, stake_account_reason, withdraw_authority,
+        NonCirculatingReason,
+    },
+    crate::bank::Bank,
+    solana_account::ReadableAccount,
+    solana_stake_interface as stake,
+    std::io::{self, Write},
+};
+
+#[derive(Serialize)]
+struct JsonAccount {
+    pubkey: String,
+    lamports: u64,
+    reason: NonCirculatingReason,
+}
+
+/// Calculates the non-circulating supply of `bank`, writing it to `writer` as JSON as the
+/// accounts are classified rather than collecting them first:
+///
+/// `{"epoch":0,"accounts":[{"pubkey":"..","lamports":0,"reason":"Lockup"},..],"lamports":0}`
+///
+/// As the total is only known once all accounts have been written, it follows the accounts. Each
+/// stake account is written from the scan as soon as it's classified, so an error leaves the
+/// output incomplete.
+pub fn calculate_and_write_json(bank: &Bank, mut writer: impl Write) -> io::Result<()> {
+    let hardcoded_accounts = non_circulating_accounts();
+    let withdraw_authority_list = withdraw_authority();
+    let clock = bank.clock();
+
+    write!(writer, "{{\"epoch\":{},\"accounts\":[", bank.epoch())?;
+    let mut lamports = 0u64;
+    let mut is_first = true;
+    let mut write_account = |writer: &mut dyn Write, account: JsonAccount| -> io::Result<()> {
+        lamports += account.lamports;
+        if !is_first {
+            writer.write_all(b",")?;
+        }
+        is_first = false;
+        serde_json::to_writer(writer, &account).map_err(io::Error::from)
+    };
+    for pubkey in hardcoded_accounts.iter() {
+        write_account(
+            &mut writer,
+            JsonAccount {
+                pubkey: pubkey.to_string(),
+                lamports: bank.get_balance(pubkey),
+                reason: NonCirculatingReason::Hardcoded,
+            },
+        )?;
+    }
+    // The scan callback can't return an error, so the first one stops further writes and is
+    // returned once the scan is done
+    let mut write_result = Ok(());
+    scan_program_accounts(bank, &stake::program::id(), |pubkey, account| {
+        if write_result.is_err() || hardcoded_accounts.contains(pubkey) {
+            return;
+        }
+        if let Some(reason) = stake_account_reason(&account, &clock, &withdraw_authority_list) {
+            write_result = write_account(
+                &mut writer,
+                JsonAccount {
+                    pubkey: pubkey.to_string(),
+                    lamports: account.lamports(),
+                    reason,
+                },
+            );
+        }
+    })
+    .map_err(io::Error::other)?;
+    write_result?;
+    write!(writer, "],\"lamports\":{lamports}}}")?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{super::tests::new_stake_account, *},
+        crate::non_circulating_supply::calculate_non_circulating_supply,
+        solana_genesis_config::GenesisConfig,
+        solana_pubkey::Pubkey,
+        solana_stake_interface::state::Lockup,
+        std::collections::BTreeMap,
+    };
+
+    #[test]
+    fn test_calculate_and_write_json() {
+        let lockup = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let mut accounts = BTreeMap::new();
+        for lamports in 1..=5 {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(lamports, &solana_pubkey::new_rand(), lockup),
+            );
+        }
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(6, &solana_pubkey::new_rand(), Lockup::default()),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let expected = calculate_non_circulating_supply(&bank).unwrap();
+
+        let mut buffer = vec![];
+        calculate_and_write_json(&bank, &mut buffer).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(json["lamports"], expected.lamports);
+        assert_eq!(json["epoch"], bank.epoch());
+        let json_accounts = json["accounts"].as_array().unwrap();
+        assert_eq!(json_accounts.len(), expected.accounts.len());
+        for json_account in json_accounts {
+            let pubkey: Pubkey = json_account["pubkey"].as_str().unwrap().parse().unwrap();
+            assert_eq!(json_account["lamports"], expected.balances[&pubkey]);
+        }
+    }
+
+    /// Writer accepting `capacity` bytes before failing
+    struct FailingWriter {
+        capacity: usize,
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.len() > self.capacity {
+                return Err(io::Error::other("out of space"));
+            }
+            self.capacity -= buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_calculate_and_write_json_write_error() {
+        let mut accounts = BTreeMap::new();
+        for lamports in 1..=5 {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    &solana_pubkey::new_rand(),
+                    Lockup {
+                        epoch: 1,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+
+        // Fails partway through the accounts
+        let err = calculate_and_write_json(&bank, FailingWriter { capacity: 64 }).unwrap_err();
+        assert_eq!(err.to_string(), "out of space");
+    }
+}
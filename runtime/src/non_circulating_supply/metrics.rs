@@ -0,0 +1,190 @@
+use {
+    super::calculate_non_circulating_supply,
+    crate::bank::Bank,
+    log::*,
+    solana_clock::Epoch,
+    std::{
+        sync::{Arc, Mutex},
+        thread::{Builder, JoinHandle},
+    },
+};
+
+/// Records the supply of the first bank of each epoch as a `supply` datapoint, at most once per
+/// epoch. Banks of competing forks entering the same epoch, or of an epoch that has already been
+/// passed, are ignored.
+#[derive(Debug, Default)]
+pub struct SupplyMetricsRecorder {
+    /// Most recent epoch a datapoint was recorded, or is being recorded, for
+    last_recorded_epoch: Mutex<Option<Epoch>>,
+}
+
+impl SupplyMetricsRecorder {
+    /// Records the supply of `bank` unless a datapoint has already been recorded for its epoch or
+    /// a later one. Returns whether a datapoint was recorded.
+    pub fn record_new_epoch(&self, bank: &Bank) -> bool {
+        let epoch = bank.epoch();
+        // Claim the epoch before scanning, so concurrent callers for the same epoch can't both
+        // record without holding the lock for the whole scan
+        let previous_epoch = {
+            let mut last_recorded_epoch = self.last_recorded_epoch.lock().unwrap();
+            if last_recorded_epoch.is_some_and(|last_recorded_epoch| last_recorded_epoch >= epoch) {
+                return false;
+            }
+            last_recorded_epoch.replace(epoch)
+        };
+        let supply = match calculate_non_circulating_supply(bank) {
+            Ok(supply) => supply,
+            Err(err) => {
+                warn!("Failed to record the supply of epoch {epoch}: {err}");
+                // Release the claim, unless a later epoch has been claimed in the meantime
+                let mut last_recorded_epoch = self.last_recorded_epoch.lock().unwrap();
+                if *last_recorded_epoch == Some(epoch) {
+                    *last_recorded_epoch = previous_epoch;
+                }
+                return false;
+            }
+        };
+        let total = supply.total_supply(bank.capitalization());
+        datapoint_info!(
+            "supply",
+            ("epoch", epoch, i64),
+            ("slot", bank.slot(), i64),
+            ("total_lamports", total, i64),
+            (
+                "circulating_lamports",
+                total.saturating_sub(supply.lamports),
+                i64
+            ),
+            ("non_circulating_lamports", supply.lamports, i64),
+        );
+        true
+    }
+
+    /// Records the supply of `bank` like `record_new_epoch()`, but on a background thread, so
+    /// that the stake account scan doesn't stall the caller, e.g. `BankForks::set_root()`. The
+    /// thread keeps neither the recorder nor the bank alive; nothing is recorded if either is
+    /// dropped before it gets to run.
+    pub fn spawn_record_new_epoch(self: &Arc<Self>, bank: Arc<Bank>) -> JoinHandle<()> {
+        let recorder = Arc::downgrade(self);
+        let weak_bank = Arc::downgrade(&bank);
+        drop(bank);
+        Builder::new()
+            .name("solSupplyMetric".to_string())
+            .spawn(move || {
+                let Some(bank) = weak_bank.upgrade() else {
+                    return;
+                };
+                let Some(recorder) = recorder.upgrade() else {
+                    return;
+                };
+                recorder.record_new_epoch(&bank);
+            })
+            .unwrap()
+    }
+
+    pub fn last_recorded_epoch(&self) -> Option<Epoch> {
+        *self.last_recorded_epoch.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::bank_forks::BankForks,
+        solana_genesis_config::GenesisConfig,
+        solana_pubkey::Pubkey,
+        std::{
+            thread::sleep,
+            time::{Duration, Instant},
+        },
+    };
+
+    #[test]
+    fn test_record_new_epoch_once_across_forks() {
+        let bank = Arc::new(Bank::new_for_tests(&GenesisConfig::default()));
+        let first_slot_in_epoch_1 = bank.epoch_schedule().get_first_slot_in_epoch(1);
+        let recorder = SupplyMetricsRecorder::default();
+        assert!(recorder.record_new_epoch(&bank));
+        assert!(!recorder.record_new_epoch(&bank));
+
+        // Two forks entering epoch 1
+        let fork_a = Bank::new_from_parent(bank.clone(), &Pubkey::default(), first_slot_in_epoch_1);
+        let fork_b = Bank::new_from_parent(bank, &Pubkey::default(), first_slot_in_epoch_1 + 1);
+        assert_eq!(fork_a.epoch(), 1);
+        assert_eq!(fork_b.epoch(), 1);
+        assert!(recorder.record_new_epoch(&fork_a));
+        assert!(!recorder.record_new_epoch(&fork_b));
+        assert_eq!(recorder.last_recorded_epoch(), Some(1));
+    }
+
+    #[test]
+    fn test_spawn_record_new_epoch() {
+        let bank = Arc::new(Bank::new_for_tests(&GenesisConfig::default()));
+        let recorder = Arc::new(SupplyMetricsRecorder::default());
+        recorder
+            .spawn_record_new_epoch(bank.clone())
+            .join()
+            .unwrap();
+        assert_eq!(recorder.last_recorded_epoch(), Some(0));
+
+        // Nothing is recorded for a bank dropped before the thread runs
+        let first_slot_in_epoch_1 = bank.epoch_schedule().get_first_slot_in_epoch(1);
+        let dropped_bank = Arc::new(Bank::new_from_parent(
+            bank,
+            &Pubkey::default(),
+            first_slot_in_epoch_1,
+        ));
+        recorder
+            .spawn_record_new_epoch(dropped_bank)
+            .join()
+            .unwrap();
+        assert_eq!(recorder.last_recorded_epoch(), Some(0));
+    }
+
+    #[test]
+    fn test_record_new_epoch_on_root() {
+        let bank = Bank::new_for_tests(&GenesisConfig::default());
+        let first_slot_in_epoch_1 = bank.epoch_schedule().get_first_slot_in_epoch(1);
+        let bank_forks = BankForks::new_rw_arc(bank);
+        let recorder = Arc::new(SupplyMetricsRecorder::default());
+        let mut bank_forks = bank_forks.write().unwrap();
+        bank_forks.set_supply_metrics_recorder(recorder.clone());
+
+        // Rooting a bank within the same epoch records nothing
+        let bank0 = bank_forks.root_bank();
+        let bank1 = bank_forks
+            .insert(Bank::new_from_parent(bank0, &Pubkey::default(), 1))
+            .clone_without_scheduler();
+        bank_forks.set_root(1, None, None).unwrap();
+        assert_eq!(recorder.last_recorded_epoch(), None);
+
+        let bank2 = bank_forks
+            .insert(Bank::new_from_parent(
+                bank1,
+                &Pubkey::default(),
+                first_slot_in_epoch_1,
+            ))
+            .clone_without_scheduler();
+        bank_forks.insert(Bank::new_from_parent(
+            bank2,
+            &Pubkey::default(),
+            first_slot_in_epoch_1 + 1,
+        ));
+        bank_forks
+            .set_root(first_slot_in_epoch_1, None, None)
+            .unwrap();
+        // Recorded on a background thread
+        let start = Instant::now();
+        while recorder.last_recorded_epoch().is_none() {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            sleep(Duration::from_millis(10));
+        }
+        assert_eq!(recorder.last_recorded_epoch(), Some(1));
+        // Rooting a descendant in the same epoch doesn't record again
+        bank_forks
+            .set_root(first_slot_in_epoch_1 + 1, None, None)
+            .unwrap();
+        assert!(!recorder.record_new_epoch(&bank_forks.root_bank()));
+    }
+}
@@ -0,0 +1,79 @@
+use {
+    super::NonCirculatingSupply,
+    solana_pubkey::Pubkey,
+    std::{
+        io::{self, BufWriter, Write},
+        path::Path,
+    },
+    tempfile::NamedTempFile,
+};
+
+/// Length of the header preceding the pubkeys in a file written by [`write_to_mmap`]
+pub const MMAP_HEADER_LEN: usize = 24;
+
+/// Writes `supply` to `path` for external readers to memory-map, in the layout:
+///
+/// | offset | length     | contents                                      |
+/// |--------|------------|-----------------------------------------------|
+/// | 0      | 8          | non-circulating lamports, little-endian `u64` |
+/// | 8      | 8          | number of accounts `n`, little-endian `u64`   |
+/// | 16     | 8          | epoch, little-endian `u64`                    |
+/// | 24     | `32 * n`   | account pubkeys, sorted                       |
+///
+/// The file is written next to `path` and renamed into place, so readers never observe a
+/// partially written file.
+pub fn write_to_mmap(supply: &NonCirculatingSupply, path: &Path) -> io::Result<()> {
+    let mut accounts = supply.accounts.clone();
+    accounts.sort_unstable();
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let file = NamedTempFile::new_in(dir)?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(&supply.lamports.to_le_bytes())?;
+    writer.write_all(&(accounts.len() as u64).to_le_bytes())?;
+    writer.write_all(&supply.epoch.to_le_bytes())?;
+    for pubkey in accounts.iter() {
+        writer.write_all(pubkey.as_ref())?;
+    }
+    let file = writer.into_inner().map_err(|err| err.into_error())?;
+    file.as_file().sync_all()?;
+    file.persist(path).map_err(|err| err.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, memmap2::Mmap};
+
+    #[test]
+    fn test_write_to_mmap() {
+        let mut accounts: Vec<Pubkey> = (0..3).map(|_| solana_pubkey::new_rand()).collect();
+        let mut supply = NonCirculatingSupply::new_for_tests(1_234, accounts.clone(), None, None);
+        supply.epoch = 7;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("supply");
+        write_to_mmap(&supply, &path).unwrap();
+        // Rewriting replaces the file in place
+        write_to_mmap(&supply, &path).unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let mmap = unsafe { Mmap::map(&file).unwrap() };
+        let read_u64 =
+            |offset: usize| u64::from_le_bytes(mmap[offset..offset + 8].try_into().unwrap());
+        assert_eq!(read_u64(0), 1_234);
+        assert_eq!(read_u64(8), 3);
+        assert_eq!(read_u64(16), 7);
+        assert_eq!(mmap.len(), MMAP_HEADER_LEN + 3 * 32);
+
+        accounts.sort_unstable();
+        let read_pubkey = |index: usize| {
+            let offset = MMAP_HEADER_LEN + index * 32;
+            Pubkey::try_from(&mmap[offset..offset + 32]).unwrap()
+        };
+        assert_eq!(read_pubkey(0), accounts[0]);
+        assert_eq!(read_pubkey(2), accounts[2]);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
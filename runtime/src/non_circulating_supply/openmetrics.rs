@@ -0,0 +1,101 @@
+use {
+    super::calculate_non_circulating_supply, crate::bank::Bank,
+    solana_accounts_db::accounts_index::ScanResult, std::fmt::Write,
+};
+
+/// Renders the supply of `bank` as an OpenMetrics text exposition, ready to be served to a
+/// scraper without a metrics registry:
+///
+/// ```text
+/// # TYPE supply_circulating_lamports gauge
+/// # HELP supply_circulating_lamports Circulating lamports.
+/// supply_circulating_lamports 0
+/// ...
+/// # EOF
+/// ```
+pub fn supply_openmetrics(bank: &Bank) -> ScanResult<String> {
+    let supply = calculate_non_circulating_supply(bank)?;
+    let gauges = [
+        (
+            "supply_circulating_lamports",
+            "Circulating lamports.",
+            supply.circulating_supply(bank.capitalization()),
+        ),
+        (
+            "supply_non_circulating_lamports",
+            "Non-circulating lamports.",
+            supply.lamports,
+        ),
+        (
+            "supply_non_circulating_accounts",
+            "Number of non-circulating accounts.",
+            supply.reasons.len() as u64,
+        ),
+    ];
+    let mut exposition = String::new();
+    for (name, help, value) in gauges {
+        // Writing to a `String` can't fail
+        let _ = write!(
+            exposition,
+            "# TYPE {name} gauge\n# HELP {name} {help}\n{name} {value}\n"
+        );
+    }
+    exposition.push_str("# EOF\n");
+    Ok(exposition)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{super::tests::new_stake_account, *},
+        solana_genesis_config::GenesisConfig,
+        solana_stake_interface::state::Lockup,
+        std::collections::BTreeMap,
+    };
+
+    #[test]
+    fn test_supply_openmetrics() {
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(
+                500,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let supply = calculate_non_circulating_supply(&bank).unwrap();
+
+        let exposition = supply_openmetrics(&bank).unwrap();
+        assert!(exposition.ends_with("# EOF\n"));
+        let samples: BTreeMap<&str, u64> = exposition
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| {
+                let (name, value) = line.split_once(' ').unwrap();
+                (name, value.parse().unwrap())
+            })
+            .collect();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples["supply_non_circulating_lamports"], supply.lamports);
+        assert_eq!(
+            samples["supply_circulating_lamports"],
+            bank.capitalization() - supply.lamports
+        );
+        assert_eq!(
+            samples["supply_non_circulating_accounts"],
+            supply.reasons.len() as u64
+        );
+        for name in samples.keys() {
+            assert!(exposition.contains(&format!("# TYPE {name} gauge\n")));
+        }
+    }
+}
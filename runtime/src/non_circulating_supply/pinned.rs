@@ -0,0 +1,208 @@
+use {
+    crate::bank::Bank,
+    solana_accounts_db::ancestors::Ancestors,
+    solana_clock::{BankId, Slot},
+    solana_pubkey::Pubkey,
+    std::sync::Arc,
+};
+
+/// Read-only view of the accounts of a frozen bank, so that a long calculation doesn't see the
+/// writes made to the bank it was started from, or to its unrooted descendants, while it runs.
+///
+/// This isn't a snapshot: reads go through the pinned bank's ancestors, and once the node roots a
+/// slot past the pinned one, loads and scans through the view can return the newer rooted
+/// versions of accounts. Holding the view doesn't keep the pinned versions from being cleaned.
+#[derive(Clone, Debug)]
+pub struct PinnedAccountsView {
+    bank: Arc<Bank>,
+}
+
+impl PinnedAccountsView {
+    /// Pins the accounts of `bank` if it's frozen, or otherwise of its parent, as the accounts of
+    /// an unfrozen bank can still change. Returns `None` for an unfrozen bank without a parent.
+    pub fn new(bank: &Arc<Bank>) -> Option<Self> {
+        if bank.is_frozen() {
+            return Some(Self { bank: bank.clone() });
+        }
+        bank.parent().map(|bank| Self { bank })
+    }
+
+    /// Slot of the bank whose accounts are pinned
+    pub fn slot(&self) -> Slot {
+        self.bank.slot()
+    }
+
+    pub(super) fn get_balance(&self, pubkey: &Pubkey) -> u64 {
+        self.bank.get_balance(pubkey)
+    }
+
+    /// Ancestors and id of the pinned bank, to scan its accounts with
+    pub(super) fn ancestors(&self) -> (&Ancestors, BankId) {
+        (&self.bank.ancestors, self.bank.bank_id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{super::tests::new_stake_account, *},
+        crate::non_circulating_supply::{
+            calculate_non_circulating_supply, calculate_non_circulating_supply_with_config,
+            NonCirculatingReason, NonCirculatingSupplyConfig, ReasonClassifier,
+        },
+        solana_account::{Account, AccountSharedData},
+        solana_clock::Clock,
+        solana_genesis_config::GenesisConfig,
+        solana_stake_interface::state::Lockup,
+        std::{
+            collections::BTreeMap,
+            sync::atomic::{AtomicBool, Ordering},
+        },
+    };
+
+    /// Seam writing `writes` to `bank` the first time the scan offers it an account, i.e. while
+    /// the calculation is under way
+    #[derive(Debug)]
+    struct WriteMidScan {
+        bank: Arc<Bank>,
+        writes: Vec<(Pubkey, AccountSharedData)>,
+        written: AtomicBool,
+    }
+
+    impl WriteMidScan {
+        fn new(bank: Arc<Bank>, writes: Vec<(Pubkey, AccountSharedData)>) -> Arc<Self> {
+            Arc::new(Self {
+                bank,
+                writes,
+                written: AtomicBool::new(false),
+            })
+        }
+    }
+
+    impl ReasonClassifier for WriteMidScan {
+        fn classify(
+            &self,
+            _pubkey: &Pubkey,
+            _account: &AccountSharedData,
+            _clock: &Clock,
+        ) -> Option<NonCirculatingReason> {
+            if !self.written.swap(true, Ordering::Relaxed) {
+                for (pubkey, account) in self.writes.iter() {
+                    self.bank.store_account(pubkey, account);
+                }
+            }
+            None
+        }
+    }
+
+    #[test]
+    fn test_pinned_accounts_view() {
+        let lockup = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            stake,
+            new_stake_account(100, &solana_pubkey::new_rand(), lockup),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+        assert!(PinnedAccountsView::new(&bank0).is_none());
+        bank0.freeze();
+        let expected = calculate_non_circulating_supply(&bank0).unwrap();
+
+        // The view of a working bank pins its frozen parent
+        let bank1 = Arc::new(Bank::new_from_parent(bank0.clone(), &Pubkey::default(), 1));
+        let view = PinnedAccountsView::new(&bank1).unwrap();
+        assert_eq!(view.slot(), 0);
+        let config = NonCirculatingSupplyConfig {
+            pinned_view: Some(view),
+            ..NonCirculatingSupplyConfig::default()
+        };
+
+        // The working bank keeps being written to after the view was pinned
+        let new_stake = solana_pubkey::new_rand();
+        for pubkey in [stake, new_stake] {
+            bank1.store_account(
+                &pubkey,
+                &new_stake_account(300, &solana_pubkey::new_rand(), lockup).into(),
+            );
+        }
+        let live = calculate_non_circulating_supply(&bank1).unwrap();
+        assert_eq!(live.lamports, expected.lamports + 200 + 300);
+
+        let pinned = calculate_non_circulating_supply_with_config(&bank1, &config).unwrap();
+        assert_eq!(pinned.lamports, expected.lamports);
+        assert_eq!(pinned.balances[&stake], 100);
+        assert!(!pinned.reasons.contains_key(&new_stake));
+    }
+
+    #[test]
+    fn test_pinned_accounts_view_written_mid_scan() {
+        let lockup = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let stake = solana_pubkey::new_rand();
+        let reserve = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            stake,
+            new_stake_account(100, &solana_pubkey::new_rand(), lockup),
+        );
+        // Circulating, so that the seam is offered it
+        accounts.insert(
+            solana_pubkey::new_rand(),
+            new_stake_account(50, &solana_pubkey::new_rand(), Lockup::default()),
+        );
+        accounts.insert(reserve, Account::new(1_000, 0, &Pubkey::default()));
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank0 = Arc::new(Bank::new_for_tests(&genesis_config));
+        bank0.freeze();
+        let bank1 = Arc::new(Bank::new_from_parent(bank0, &Pubkey::default(), 1));
+        let writes = |reserve_lamports| {
+            vec![
+                (
+                    reserve,
+                    AccountSharedData::new(reserve_lamports, 0, &Pubkey::default()),
+                ),
+                (
+                    stake,
+                    new_stake_account(300, &solana_pubkey::new_rand(), lockup).into(),
+                ),
+            ]
+        };
+
+        let seam = WriteMidScan::new(bank1.clone(), writes(5_000));
+        let config = NonCirculatingSupplyConfig {
+            additional_non_circulating_accounts: vec![reserve],
+            classifiers: vec![seam.clone()],
+            pinned_view: PinnedAccountsView::new(&bank1),
+            ..NonCirculatingSupplyConfig::default()
+        };
+        let pinned = calculate_non_circulating_supply_with_config(&bank1, &config).unwrap();
+        assert!(seam.written.load(Ordering::Relaxed));
+        assert_eq!(bank1.get_balance(&reserve), 5_000);
+        assert_eq!(pinned.balances[&reserve], 1_000);
+        assert_eq!(pinned.balances[&stake], 100);
+
+        // Without the view, the write made mid-scan leaks into the result
+        let seam = WriteMidScan::new(bank1.clone(), writes(7_000));
+        let config = NonCirculatingSupplyConfig {
+            classifiers: vec![seam.clone()],
+            pinned_view: None,
+            ..config
+        };
+        let live = calculate_non_circulating_supply_with_config(&bank1, &config).unwrap();
+        assert!(seam.written.load(Ordering::Relaxed));
+        assert_eq!(live.balances[&reserve], 7_000);
+    }
+}
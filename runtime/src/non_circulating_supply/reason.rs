@@ -0,0 +1,313 @@
+use {
+    super::withdraw_authority,
+    solana_account::{AccountSharedData, ReadableAccount},
+    solana_clock::Clock,
+    solana_pubkey::Pubkey,
+    solana_stake_interface::{self as stake, state::StakeStateV2},
+    solana_stake_program::stake_state,
+    std::fmt,
+};
+
+/// Why an account is considered non-circulating
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum NonCirculatingReason {
+    /// Listed in `non_circulating_accounts()`
+    Hardcoded,
+    /// Stake account whose lockup is in force
+    Lockup,
+    /// Stake account controlled by a withdraw authority listed in `withdraw_authority()`
+    WithdrawAuthority,
+    /// Stake account whose stake is still deactivating, with `count_cooldown_stake` set
+    Cooldown,
+    /// Reason assigned by a downstream `ReasonClassifier`
+    Custom(&'static str),
+}
+
+/// Decides whether accounts owned by `program_id()` are non-circulating. The scan offers each
+/// such account to the built-in stake classifier first, then to the configured classifiers in
+/// order, and records the first reason returned.
+pub trait ReasonClassifier: fmt::Debug + Send + Sync {
+    /// Program whose accounts are offered to `classify`
+    fn program_id(&self) -> Pubkey {
+        stake::program::id()
+    }
+
+    fn classify(
+        &self,
+        pubkey: &Pubkey,
+        account: &AccountSharedData,
+        clock: &Clock,
+    ) -> Option<NonCirculatingReason>;
+
+    /// Lamports of a classified account that count as non-circulating, for classifiers that
+    /// only lock part of an account's balance
+    fn non_circulating_lamports(
+        &self,
+        _pubkey: &Pubkey,
+        account: &AccountSharedData,
+        _clock: &Clock,
+    ) -> u64 {
+        account.lamports()
+    }
+}
+
+/// Built-in classification of stake accounts by lockup and withdraw authority
+#[derive(Debug)]
+pub struct StakeReasonClassifier {
+    withdraw_authority_list: Vec<Pubkey>,
+    hard_custodians: Option<Vec<Pubkey>>,
+}
+
+impl StakeReasonClassifier {
+    pub fn new(withdraw_authority_list: Vec<Pubkey>) -> Self {
+        Self {
+            withdraw_authority_list,
+            hard_custodians: None,
+        }
+    }
+
+    /// Treats lockups with a custodian outside `hard_custodians` as liftable, and so not by
+    /// themselves making an account non-circulating
+    pub fn with_hard_custodians(mut self, hard_custodians: Vec<Pubkey>) -> Self {
+        self.hard_custodians = Some(hard_custodians);
+        self
+    }
+
+    fn is_lockup_liftable(&self, account: &AccountSharedData) -> bool {
+        let Some(hard_custodians) = &self.hard_custodians else {
+            return false;
+        };
+        stake_state::lockup_from(account).is_some_and(|lockup| {
+            lockup.custodian != Pubkey::default() && !hard_custodians.contains(&lockup.custodian)
+        })
+    }
+}
+
+impl Default for StakeReasonClassifier {
+    fn default() -> Self {
+        Self::new(withdraw_authority())
+    }
+}
+
+impl ReasonClassifier for StakeReasonClassifier {
+    fn classify(
+        &self,
+        _pubkey: &Pubkey,
+        account: &AccountSharedData,
+        clock: &Clock,
+    ) -> Option<NonCirculatingReason> {
+        let reason = stake_account_reason(account, clock, &self.withdraw_authority_list)?;
+        if reason != NonCirculatingReason::Lockup || !self.is_lockup_liftable(account) {
+            return Some(reason);
+        }
+        let withdrawer = stake_state::meta_from(account)?.authorized.withdrawer;
+        self.withdraw_authority_list
+            .contains(&withdrawer)
+            .then_some(NonCirculatingReason::WithdrawAuthority)
+    }
+}
+
+/// Classifies the accounts of a token-lock program, e.g. a wrapper locking SOL behind SPL tokens,
+/// counting the locked amount recorded in each account rather than its whole balance.
+///
+/// The locked amount is read as a little-endian `u64` at `locked_lamports_offset` in the
+/// account data, and capped at the account's balance. Accounts locking nothing, or too short to
+/// hold the amount, are circulating.
+#[derive(Debug)]
+pub struct TokenLockClassifier {
+    program_id: Pubkey,
+    locked_lamports_offset: usize,
+}
+
+impl TokenLockClassifier {
+    pub const REASON: &'static str = "token-lock";
+
+    pub fn new(program_id: Pubkey, locked_lamports_offset: usize) -> Self {
+        Self {
+            program_id,
+            locked_lamports_offset,
+        }
+    }
+
+    fn locked_lamports(&self, account: &AccountSharedData) -> Option<u64> {
+        let end = self.locked_lamports_offset.checked_add(8)?;
+        let bytes = account.data().get(self.locked_lamports_offset..end)?;
+        let locked_lamports = u64::from_le_bytes(bytes.try_into().ok()?);
+        (locked_lamports > 0).then(|| locked_lamports.min(account.lamports()))
+    }
+}
+
+impl ReasonClassifier for TokenLockClassifier {
+    fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    fn classify(
+        &self,
+        _pubkey: &Pubkey,
+        account: &AccountSharedData,
+        _clock: &Clock,
+    ) -> Option<NonCirculatingReason> {
+        self.locked_lamports(account)
+            .map(|_locked_lamports| NonCirculatingReason::Custom(Self::REASON))
+    }
+
+    fn non_circulating_lamports(
+        &self,
+        _pubkey: &Pubkey,
+        account: &AccountSharedData,
+        _clock: &Clock,
+    ) -> u64 {
+        self.locked_lamports(account).unwrap_or_default()
+    }
+}
+
+/// Classifies the accounts of a linear-vesting escrow program, counting the portion of each
+/// escrow that is still unvested at the bank's clock.
+///
+/// The escrow is read as three little-endian fields starting at `schedule_offset` in the account
+/// data: the `i64` start and end unix timestamps of the vesting, followed by the `u64` total
+/// amount. The amount vests linearly between the two timestamps, so it is entirely locked before
+/// the start and entirely vested at the end. The locked portion is capped at the account's
+/// balance. Fully vested accounts, and accounts too short to hold the schedule, are circulating.
+#[derive(Debug)]
+pub struct LinearVestingClassifier {
+    program_id: Pubkey,
+    schedule_offset: usize,
+}
+
+impl LinearVestingClassifier {
+    pub const REASON: &'static str = "linear-vesting";
+    /// Length of the `(start_timestamp, end_timestamp, total_amount)` schedule
+    pub const SCHEDULE_LEN: usize = 24;
+
+    pub fn new(program_id: Pubkey, schedule_offset: usize) -> Self {
+        Self {
+            program_id,
+            schedule_offset,
+        }
+    }
+
+    fn locked_lamports(&self, account: &AccountSharedData, clock: &Clock) -> Option<u64> {
+        let end = self.schedule_offset.checked_add(Self::SCHEDULE_LEN)?;
+        let schedule = account.data().get(self.schedule_offset..end)?;
+        let start_timestamp = i64::from_le_bytes(schedule[0..8].try_into().ok()?);
+        let end_timestamp = i64::from_le_bytes(schedule[8..16].try_into().ok()?);
+        let total_amount = u64::from_le_bytes(schedule[16..24].try_into().ok()?);
+        let now = clock.unix_timestamp;
+        let locked_lamports = if now >= end_timestamp {
+            0
+        } else if now <= start_timestamp {
+            total_amount
+        } else {
+            let remaining = u128::from(end_timestamp.abs_diff(now));
+            let duration = u128::from(end_timestamp.abs_diff(start_timestamp));
+            // `remaining < duration`, so the result fits in a `u64`
+            (u128::from(total_amount) * remaining / duration) as u64
+        };
+        (locked_lamports > 0).then(|| locked_lamports.min(account.lamports()))
+    }
+}
+
+impl ReasonClassifier for LinearVestingClassifier {
+    fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    fn classify(
+        &self,
+        _pubkey: &Pubkey,
+        account: &AccountSharedData,
+        clock: &Clock,
+    ) -> Option<NonCirculatingReason> {
+        self.locked_lamports(account, clock)
+            .map(|_locked_lamports| NonCirculatingReason::Custom(Self::REASON))
+    }
+
+    fn non_circulating_lamports(
+        &self,
+        _pubkey: &Pubkey,
+        account: &AccountSharedData,
+        clock: &Clock,
+    ) -> u64 {
+        self.locked_lamports(account, clock).unwrap_or_default()
+    }
+}
+
+/// Classifies the frozen accounts of a token program, e.g. wrapped-SOL-like accounts whose freeze
+/// authority has frozen them, counting their whole balance.
+///
+/// An account is frozen when the byte at `state_offset` in its data equals `frozen_state`. For
+/// SPL token accounts that's the `AccountState` byte, see `SPL_TOKEN_STATE_OFFSET` and
+/// `SPL_TOKEN_FROZEN_STATE`. Accounts too short to hold the byte are circulating.
+#[derive(Debug)]
+pub struct FrozenAccountClassifier {
+    program_id: Pubkey,
+    state_offset: usize,
+    frozen_state: u8,
+}
+
+impl FrozenAccountClassifier {
+    pub const REASON: &'static str = "frozen";
+    /// Offset of the `AccountState` byte in SPL token accounts, after the mint, owner, amount and
+    /// delegate
+    pub const SPL_TOKEN_STATE_OFFSET: usize = 108;
+    /// `AccountState::Frozen`
+    pub const SPL_TOKEN_FROZEN_STATE: u8 = 2;
+
+    pub fn new(program_id: Pubkey, state_offset: usize, frozen_state: u8) -> Self {
+        Self {
+            program_id,
+            state_offset,
+            frozen_state,
+        }
+    }
+
+    /// Classifies the frozen accounts of the SPL token-like program `program_id`
+    pub fn spl_token(program_id: Pubkey) -> Self {
+        Self::new(
+            program_id,
+            Self::SPL_TOKEN_STATE_OFFSET,
+            Self::SPL_TOKEN_FROZEN_STATE,
+        )
+    }
+}
+
+impl ReasonClassifier for FrozenAccountClassifier {
+    fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    fn classify(
+        &self,
+        _pubkey: &Pubkey,
+        account: &AccountSharedData,
+        _clock: &Clock,
+    ) -> Option<NonCirculatingReason> {
+        (account.data().get(self.state_offset) == Some(&self.frozen_state))
+            .then_some(NonCirculatingReason::Custom(Self::REASON))
+    }
+}
+
+pub(super) fn stake_account_reason(
+    account: &AccountSharedData,
+    clock: &Clock,
+    withdraw_authority_list: &[Pubkey],
+) -> Option<NonCirculatingReason> {
+    // Never decode an account that isn't owned by the stake program as a stake account
+    if account.owner() != &stake::program::id() {
+        return None;
+    }
+    let meta = match stake_state::from(account).unwrap_or_default() {
+        StakeStateV2::Initialized(meta) => meta,
+        StakeStateV2::Stake(meta, _stake, _stake_flags) => meta,
+        _ => return None,
+    };
+    if meta.lockup.is_in_force(clock, None) {
+        Some(NonCirculatingReason::Lockup)
+    } else if withdraw_authority_list.contains(&meta.authorized.withdrawer) {
+        Some(NonCirculatingReason::WithdrawAuthority)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,163 @@
+use {
+    super::{
+        classify_prefiltered, load_stake_accounts, non_circulating_accounts, NonCirculatingSupply,
+        NonCirculatingSupplyConfig, StaticLists,
+    },
+    crate::bank::Bank,
+    solana_account::{AccountSharedData, ReadableAccount},
+    solana_accounts_db::accounts_index::ScanResult,
+    solana_clock::Clock,
+    solana_pubkey::Pubkey,
+    solana_stake_interface as stake,
+    std::collections::HashMap,
+};
+
+/// Minimal read access to the accounts the default supply calculation needs, so that it can run
+/// against account state other than a `Bank`, e.g. in an offline audit tool
+pub trait SupplySource {
+    /// All accounts owned by the stake program
+    fn stake_accounts(&self) -> ScanResult<Vec<(Pubkey, AccountSharedData)>>;
+
+    /// Balance of `pubkey`, zero if it doesn't exist
+    fn get_balance(&self, pubkey: &Pubkey) -> u64;
+
+    /// Clock lockups are evaluated against
+    fn clock(&self) -> Clock;
+}
+
+impl SupplySource for Bank {
+    fn stake_accounts(&self) -> ScanResult<Vec<(Pubkey, AccountSharedData)>> {
+        load_stake_accounts(self)
+    }
+
+    fn get_balance(&self, pubkey: &Pubkey) -> u64 {
+        Bank::get_balance(self, pubkey)
+    }
+
+    fn clock(&self) -> Clock {
+        Bank::clock(self)
+    }
+}
+
+/// Read-only copy of account state detached from any bank, e.g. as loaded from a snapshot
+/// archive by an offline tool
+#[derive(Clone, Debug, Default)]
+pub struct AccountsSnapshot {
+    accounts: HashMap<Pubkey, AccountSharedData>,
+    clock: Clock,
+}
+
+impl AccountsSnapshot {
+    pub fn new(
+        clock: Clock,
+        accounts: impl IntoIterator<Item = (Pubkey, AccountSharedData)>,
+    ) -> Self {
+        Self {
+            accounts: accounts.into_iter().collect(),
+            clock,
+        }
+    }
+}
+
+impl SupplySource for AccountsSnapshot {
+    fn stake_accounts(&self) -> ScanResult<Vec<(Pubkey, AccountSharedData)>> {
+        Ok(self
+            .accounts
+            .iter()
+            .filter(|(_pubkey, account)| {
+                // Accounts without lamports don't exist
+                account.lamports() > 0 && account.owner() == &stake::program::id()
+            })
+            .map(|(pubkey, account)| (*pubkey, account.clone()))
+            .collect())
+    }
+
+    fn get_balance(&self, pubkey: &Pubkey) -> u64 {
+        self.accounts
+            .get(pubkey)
+            .map_or(0, |account| account.lamports())
+    }
+
+    fn clock(&self) -> Clock {
+        self.clock.clone()
+    }
+}
+
+/// Calculates the non-circulating supply of `source` under the default configuration, like
+/// `calculate_non_circulating_supply()` does for a bank
+pub fn calculate_non_circulating_supply_from(
+    source: &impl SupplySource,
+) -> ScanResult<NonCirculatingSupply> {
+    let clock = source.clock();
+    let mut supply = classify_prefiltered(
+        &source.stake_accounts()?,
+        &clock,
+        &NonCirculatingSupplyConfig::default(),
+    )?;
+    let static_lists = StaticLists::new();
+    for pubkey in non_circulating_accounts() {
+        supply.include_hardcoded_account(&static_lists, pubkey, source.get_balance(&pubkey));
+    }
+    Ok(supply)
+}
+
+#[cfg(all(test, feature = "mainnet-lists"))]
+mod tests {
+    use {
+        super::{super::tests::new_stake_account, *},
+        crate::non_circulating_supply::{calculate_non_circulating_supply, withdraw_authority},
+        solana_account::Account,
+        solana_genesis_config::GenesisConfig,
+        solana_stake_interface::state::Lockup,
+        std::collections::BTreeMap,
+    };
+
+    #[test]
+    fn test_calculate_non_circulating_supply_from_snapshot() {
+        let mut accounts = BTreeMap::new();
+        for pubkey in non_circulating_accounts() {
+            accounts.insert(pubkey, Account::new(1_000, 0, &Pubkey::default()));
+        }
+        for (lamports, withdrawer, epoch) in [
+            (10, solana_pubkey::new_rand(), 1),
+            (20, solana_pubkey::new_rand(), 0),
+            (40, withdraw_authority()[0], 0),
+        ] {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(
+                    lamports,
+                    &withdrawer,
+                    Lockup {
+                        epoch,
+                        ..Lockup::default()
+                    },
+                ),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let expected = calculate_non_circulating_supply(&bank).unwrap();
+
+        // Fixture standing in for accounts loaded from a snapshot
+        let snapshot = AccountsSnapshot::new(
+            bank.clock(),
+            genesis_config
+                .accounts
+                .iter()
+                .map(|(pubkey, account)| (*pubkey, AccountSharedData::from(account.clone()))),
+        );
+        for supply in [
+            calculate_non_circulating_supply_from(&snapshot).unwrap(),
+            calculate_non_circulating_supply_from(&bank).unwrap(),
+        ] {
+            assert_eq!(supply.lamports, expected.lamports);
+            assert_eq!(supply.reasons, expected.reasons);
+            assert_eq!(supply.balances, expected.balances);
+            assert_eq!(supply.static_lamports, expected.static_lamports);
+        }
+    }
+}
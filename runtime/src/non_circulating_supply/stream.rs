@@ -0,0 +1,184 @@
+use {
+    super::{
+        non_circulating_accounts, scan_program_accounts, stake_account_reason, withdraw_authority,
+    },
+    crate::bank::Bank,
+    futures::{stream, Stream},
+    solana_account::ReadableAccount,
+    solana_accounts_db::accounts_index::{ScanError, ScanResult},
+    solana_pubkey::Pubkey,
+    solana_stake_interface as stake,
+    std::sync::Arc,
+    tokio::{
+        sync::mpsc::{self, Receiver, Sender},
+        task::JoinHandle,
+    },
+};
+
+/// Maximum number of classified accounts buffered ahead of the consumer, beyond which the scan
+/// waits for the stream to be polled
+const STREAM_CHANNEL_CAPACITY: usize = 4096;
+
+enum BlockingStreamState<F, T> {
+    /// `f` has yet to be spawned, which happens on the first poll
+    Unstarted(F),
+    Running {
+        receiver: Receiver<ScanResult<T>>,
+        task: JoinHandle<()>,
+    },
+    Done,
+}
+
+/// Streams the non-circulating accounts of `bank` with their counted lamports. The stake program
+/// is scanned on tokio's blocking thread pool, so the scan doesn't stall the async runtime, and
+/// each account is yielded as soon as the scan classifies it rather than once all stake accounts
+/// are loaded.
+///
+/// Items are `ScanResult`s rather than bare `(Pubkey, u64)` pairs so that a failed scan, or a
+/// panic while classifying, reaches the consumer instead of looking like a complete stream: the
+/// stream then yields the error and ends.
+pub fn non_circulating_stream(bank: Arc<Bank>) -> impl Stream<Item = ScanResult<(Pubkey, u64)>> {
+    blocking_stream(move |sender| send_non_circulating_accounts(&bank, sender))
+}
+
+/// Classifies the accounts of `bank`, sending each non-circulating one to `sender` as it's
+/// classified. Nothing more is sent once the stream has been dropped.
+fn send_non_circulating_accounts(
+    bank: &Bank,
+    sender: &Sender<ScanResult<(Pubkey, u64)>>,
+) -> ScanResult<()> {
+    let hardcoded_accounts = non_circulating_accounts();
+    let withdraw_authority_list = withdraw_authority();
+    let clock = bank.clock();
+    for pubkey in hardcoded_accounts.iter() {
+        if sender
+            .blocking_send(Ok((*pubkey, bank.get_balance(pubkey))))
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+    let mut is_dropped = false;
+    scan_program_accounts(bank, &stake::program::id(), |pubkey, account| {
+        if is_dropped || hardcoded_accounts.contains(pubkey) {
+            return;
+        }
+        if stake_account_reason(&account, &clock, &withdraw_authority_list).is_some() {
+            is_dropped = sender
+                .blocking_send(Ok((*pubkey, account.lamports())))
+                .is_err();
+        }
+    })
+}
+
+/// Streams the items `f` sends while it runs on tokio's blocking thread pool. An error returned
+/// by `f`, or a panic in it, is yielded as the last item.
+fn blocking_stream<T, F>(f: F) -> impl Stream<Item = ScanResult<T>>
+where
+    T: Send + 'static,
+    F: FnOnce(&Sender<ScanResult<T>>) -> ScanResult<()> + Send + 'static,
+{
+    stream::unfold(BlockingStreamState::Unstarted(f), |state| async move {
+        let (mut receiver, task) = match state {
+            BlockingStreamState::Unstarted(f) => {
+                let (sender, receiver) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+                let task = tokio::task::spawn_blocking(move || {
+                    if let Err(err) = f(&sender) {
+                        // Unless the stream has been dropped in the meantime
+                        let _ = sender.blocking_send(Err(err));
+                    }
+                });
+                (receiver, task)
+            }
+            BlockingStreamState::Running { receiver, task } => (receiver, task),
+            BlockingStreamState::Done => return None,
+        };
+        match receiver.recv().await {
+            Some(item) => Some((item, BlockingStreamState::Running { receiver, task })),
+            // `f` is done, having dropped the sender
+            None => task.await.err().map(|err| {
+                (
+                    Err(ScanError::Aborted(format!(
+                        "non-circulating supply task failed: {err}"
+                    ))),
+                    BlockingStreamState::Done,
+                )
+            }),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{super::tests::new_stake_account, *},
+        crate::non_circulating_supply::calculate_non_circulating_supply,
+        futures::StreamExt,
+        solana_genesis_config::GenesisConfig,
+        solana_stake_interface::state::Lockup,
+        std::collections::BTreeMap,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_non_circulating_stream() {
+        let lockup = Lockup {
+            epoch: 1,
+            ..Lockup::default()
+        };
+        let mut accounts = BTreeMap::new();
+        for lamports in 1..=10 {
+            accounts.insert(
+                solana_pubkey::new_rand(),
+                new_stake_account(lamports, &solana_pubkey::new_rand(), lockup),
+            );
+        }
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Arc::new(Bank::new_for_tests(&genesis_config));
+        let expected = calculate_non_circulating_supply(&bank).unwrap();
+
+        let streamed: Vec<(Pubkey, u64)> = non_circulating_stream(bank.clone())
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<ScanResult<_>>()
+            .unwrap();
+        assert_eq!(streamed.len(), expected.accounts.len());
+        assert_eq!(
+            streamed
+                .iter()
+                .map(|(_pubkey, lamports)| lamports)
+                .sum::<u64>(),
+            expected.lamports
+        );
+
+        // Dropping the stream early stops the scan rather than leaving it blocked
+        let first: Vec<_> = non_circulating_stream(bank).take(1).collect().await;
+        assert_eq!(first.len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_blocking_stream_errors() {
+        let items: Vec<ScanResult<u64>> = blocking_stream(|sender| {
+            sender.blocking_send(Ok(1)).unwrap();
+            Err(ScanError::Aborted("scan".to_string()))
+        })
+        .collect()
+        .await;
+        assert_eq!(
+            items,
+            vec![Ok(1), Err(ScanError::Aborted("scan".to_string()))]
+        );
+
+        let items: Vec<ScanResult<u64>> = blocking_stream(|sender| {
+            sender.blocking_send(Ok(1)).unwrap();
+            panic!("scan");
+        })
+        .collect()
+        .await;
+        assert_eq!(items.len(), 2);
+        assert!(matches!(items[1], Err(ScanError::Aborted(_))));
+    }
+}
@@ -0,0 +1,134 @@
+use {
+    super::{calculate_non_circulating_supply, NonCirculatingReason, NonCirculatingSupply},
+    crate::bank::Bank,
+    solana_accounts_db::accounts_index::ScanResult,
+    solana_clock::Epoch,
+    solana_pubkey::Pubkey,
+    std::collections::BTreeMap,
+};
+
+/// Off-chain vesting schedule locking amounts of accounts until given epochs, on top of any
+/// on-chain lockups
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VestingSchedule {
+    /// Tranches of each account, as `(unlock_epoch, lamports)`
+    tranches: BTreeMap<Pubkey, Vec<(Epoch, u64)>>,
+}
+
+impl VestingSchedule {
+    pub const REASON: &'static str = "external-vesting";
+
+    /// Locks `lamports` of `pubkey` until `unlock_epoch`
+    pub fn add_tranche(&mut self, pubkey: Pubkey, unlock_epoch: Epoch, lamports: u64) {
+        self.tranches
+            .entry(pubkey)
+            .or_default()
+            .push((unlock_epoch, lamports));
+    }
+
+    /// Returns the lamports of `pubkey` still locked at `epoch`
+    pub fn locked_lamports(&self, pubkey: &Pubkey, epoch: Epoch) -> u64 {
+        self.tranches.get(pubkey).map_or(0, |tranches| {
+            tranches
+                .iter()
+                .filter(|(unlock_epoch, _lamports)| *unlock_epoch > epoch)
+                .fold(0u64, |total, (_unlock_epoch, lamports)| {
+                    total.saturating_add(*lamports)
+                })
+        })
+    }
+}
+
+/// Calculates the non-circulating supply of `bank` with the amounts `schedule` still locks
+/// added on top of the on-chain lockups. An account's counted lamports are capped at its balance,
+/// so a tranche locking an account that is already entirely non-circulating has no effect.
+pub fn apply_external_vesting(
+    bank: &Bank,
+    schedule: &VestingSchedule,
+) -> ScanResult<NonCirculatingSupply> {
+    let mut supply = calculate_non_circulating_supply(bank)?;
+    let epoch = bank.epoch();
+    for pubkey in schedule.tranches.keys() {
+        let locked_lamports = schedule.locked_lamports(pubkey, epoch);
+        if locked_lamports == 0 {
+            continue;
+        }
+        let balance = bank.get_balance(pubkey);
+        let counted = supply.balances.get(pubkey).copied().unwrap_or_default();
+        let vested_counted = counted.saturating_add(locked_lamports).min(balance);
+        if vested_counted <= counted {
+            continue;
+        }
+        supply.lamports += vested_counted - counted;
+        supply.balances.insert(*pubkey, vested_counted);
+        if !supply.reasons.contains_key(pubkey) {
+            supply.accounts.push(*pubkey);
+            supply.reasons.insert(
+                *pubkey,
+                NonCirculatingReason::Custom(VestingSchedule::REASON),
+            );
+        }
+    }
+    Ok(supply)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{super::tests::new_stake_account, *},
+        solana_account::Account,
+        solana_genesis_config::GenesisConfig,
+        solana_stake_interface::state::Lockup,
+    };
+
+    #[test]
+    fn test_apply_external_vesting() {
+        let vested = solana_pubkey::new_rand();
+        let fully_vested = solana_pubkey::new_rand();
+        let unlocked = solana_pubkey::new_rand();
+        let locked_stake = solana_pubkey::new_rand();
+        let mut accounts = BTreeMap::new();
+        for pubkey in [vested, fully_vested, unlocked] {
+            accounts.insert(pubkey, Account::new(1_000, 0, &Pubkey::default()));
+        }
+        accounts.insert(
+            locked_stake,
+            new_stake_account(
+                500,
+                &solana_pubkey::new_rand(),
+                Lockup {
+                    epoch: 1,
+                    ..Lockup::default()
+                },
+            ),
+        );
+        let genesis_config = GenesisConfig {
+            accounts,
+            ..GenesisConfig::default()
+        };
+        let bank = Bank::new_for_tests(&genesis_config);
+        let on_chain = calculate_non_circulating_supply(&bank).unwrap();
+
+        let mut schedule = VestingSchedule::default();
+        schedule.add_tranche(vested, 1, 300);
+        schedule.add_tranche(vested, 2, 100);
+        // Clamped to the account's balance
+        schedule.add_tranche(fully_vested, 1, 5_000);
+        // Already unlocked at epoch 0
+        schedule.add_tranche(unlocked, 0, 1_000);
+        // Already entirely non-circulating on-chain
+        schedule.add_tranche(locked_stake, 1, 500);
+
+        let supply = apply_external_vesting(&bank, &schedule).unwrap();
+        assert_eq!(supply.lamports, on_chain.lamports + 400 + 1_000);
+        assert_eq!(supply.balances[&vested], 400);
+        assert_eq!(supply.balances[&fully_vested], 1_000);
+        assert_eq!(supply.balances[&locked_stake], 500);
+        assert!(!supply.reasons.contains_key(&unlocked));
+        assert_eq!(
+            supply.reasons[&vested],
+            NonCirculatingReason::Custom(VestingSchedule::REASON)
+        );
+        assert_eq!(supply.reasons[&locked_stake], NonCirculatingReason::Lockup);
+    }
+}
@@ -1237,6 +1237,16 @@ pub fn add_args<'a>(app: App<'a, 'a>, default_args: &'a DefaultArgs) -> App<'a,
             .requires("enable_rpc_transaction_history")
             .help("Verifies blockstore roots on boot and fixes any gaps"),
     )
+    .arg(
+        Arg::with_name("enable_supply_metrics")
+            .long("enable-supply-metrics")
+            .takes_value(false)
+            .help(
+                "Report the non-circulating supply as a metrics datapoint at each newly rooted \
+                 epoch. Each report scans the stake program, which is a full accounts scan \
+                 without the program-id account index",
+            ),
+    )
     .arg(
         Arg::with_name("rpc_max_request_body_size")
             .long("rpc-max-request-body-size")
@@ -41,6 +41,7 @@ impl FromClapArgMatches for JsonRpcConfig {
             rpc_scan_and_fix_roots: matches.is_present("rpc_scan_and_fix_roots"),
             max_request_body_size: Some(value_t!(matches, "rpc_max_request_body_size", usize)?),
             disable_health_check: false,
+            enable_supply_metrics: matches.is_present("enable_supply_metrics"),
         })
     }
 }
@@ -301,6 +302,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn verify_args_struct_by_command_run_with_enable_supply_metrics() {
+        let default_run_args = crate::commands::run::args::RunArgs::default();
+        let expected_args = RunArgs {
+            json_rpc_config: JsonRpcConfig {
+                enable_supply_metrics: true,
+                ..default_run_args.json_rpc_config.clone()
+            },
+            ..default_run_args.clone()
+        };
+        verify_args_struct_by_command_run_with_identity_setup(
+            default_run_args,
+            vec!["--enable-supply-metrics"],
+            expected_args,
+        );
+    }
+
     #[test]
     fn verify_args_struct_by_command_run_with_rpc_max_request_body_size() {
         // long arg